@@ -0,0 +1,596 @@
+use std::{collections::HashMap, fmt};
+
+use crate::instruction::Instruction;
+
+// Assembled programs are loaded at 0x200, so that's where addresses start from
+const PROGRAM_START: u16 = 0x200;
+
+// Everything from the program start to the end of a 64 KiB address space
+const MAX_PROGRAM_SIZE: usize = 0x10000 - PROGRAM_START as usize;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssemblerError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssemblerError {}
+
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FixupKind {
+    Addr12, // Low 12 bits of an instruction word
+    Addr16, // A whole word, e.g. the operand of `i := long`
+    Byte,
+}
+
+#[derive(Debug)]
+struct Fixup {
+    offset: usize,
+    kind: FixupKind,
+    label: String,
+    line: usize,
+}
+
+/**
+ * Assemble a program written in a subset of Octo syntax into a byte image which loads at 0x200.
+ *
+ * Supported: `: label`, `:const name value`, `:alias name vX`, `:byte value`, `:org addr`,
+ * `:call target`, `:jump target`, calling a label by name, `jump`, `jump0`, `return` (or `;`),
+ * `clear`, `exit`, `hires`, `lores`, `scroll-down n`, `scroll-up n`, `scroll-left`, `scroll-right`,
+ * `bcd`, `save`/`load` (including `vX - vY` ranges), `saveflags`, `loadflags`, `sprite vX vY n`,
+ * `plane n`, `pitch := vX`, `delay := vX`, `buzzer := vX`, `i := value`, `i := long value`,
+ * `i += vX`, `i := hex vX`, `i := bighex vX`, register assignment and arithmetic
+ * (`:=`, `+=`, `-=`, `=-`, `|=`, `&=`, `^=`, `>>=`, `<<=`, `random`, `delay`, `key`), and
+ * `if <condition> then <statement>` with `==`, `!=`, `key` and `-key` conditions. Bare numbers are
+ * emitted as data bytes, and anything after a `#` on a line is a comment.
+ *
+ * As in Octo, a program with a `: main` label starts there - unless main is the very first thing in
+ * the program, it begins with a `jump main`. Programs without a main start at their first statement.
+ */
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssemblerError> {
+    let tokens = tokenize(source);
+    let mut assembler = Assembler {
+        tokens: &tokens,
+        pos: 0,
+        output: Vec::new(),
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        fixups: Vec::new(),
+    };
+
+    // Octo programs start at main, wherever it is
+    let has_main = tokens.windows(2).any(|pair| pair[0].text == ":" && pair[1].text == "main");
+    let starts_with_main = tokens.len() >= 2 && tokens[0].text == ":" && tokens[1].text == "main";
+    if has_main && !starts_with_main {
+        assembler.emit(Instruction::Jump { addr: 0 });
+        assembler.fixups.push(Fixup { offset: 0, kind: FixupKind::Addr12, label: "main".to_string(), line: tokens[0].line });
+    }
+
+    while assembler.pos < tokens.len() {
+        assembler.statement()?;
+    }
+
+    assembler.resolve_fixups()?;
+    if assembler.output.len() > MAX_PROGRAM_SIZE {
+        let line = tokens.last().map(|token| token.line).unwrap_or(1);
+        return Err(AssemblerError { line, message: format!("Program too large - {} bytes, at most {} fit in memory", assembler.output.len(), MAX_PROGRAM_SIZE) });
+    }
+    Ok(assembler.output)
+}
+
+fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let code = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line
+        };
+
+        for text in code.split_whitespace() {
+            tokens.push(Token { text, line: i + 1 });
+        }
+    }
+
+    tokens
+}
+
+struct Assembler<'a> {
+    tokens: &'a [Token<'a>],
+    pos: usize,
+    output: Vec<u8>,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, u16>,
+    aliases: HashMap<String, u8>,
+    fixups: Vec<Fixup>,
+}
+
+impl<'a> Assembler<'a> {
+    fn statement(&mut self) -> Result<(), AssemblerError> {
+        let token = self.next()?;
+
+        match token.text {
+            ":" => {
+                let name = self.next()?;
+                let addr = self.get_address(name)?;
+                if self.labels.insert(name.text.to_string(), addr).is_some() {
+                    return Err(self.error_at(name, format!("Label '{}' is already defined", name.text)));
+                }
+            },
+            ":const" => {
+                let name = self.next()?;
+                let value = self.next()?;
+                let value = self.get_constant(value)?;
+                self.constants.insert(name.text.to_string(), value);
+            },
+            ":alias" => {
+                let name = self.next()?;
+                let register = self.register()?;
+                self.aliases.insert(name.text.to_string(), register);
+            },
+            ":byte" => self.emit_value(FixupKind::Byte)?,
+            ":org" => {
+                let value = self.next()?;
+                let addr = self.get_constant(value)?;
+                if addr < self.get_address(value)? {
+                    return Err(self.error_at(value, format!(":org 0x{:04X} is behind the current address", addr)));
+                }
+                self.output.resize((addr - PROGRAM_START) as usize, 0);
+            },
+            ":call" => self.emit_with_address(Instruction::Call { addr: 0 })?,
+            ":jump" | "jump" => self.emit_with_address(Instruction::Jump { addr: 0 })?,
+            "jump0" => self.emit_with_address(Instruction::JumpWithOffset { addr: 0 })?,
+            "return" | ";" => self.emit(Instruction::Return),
+            "clear" => self.emit(Instruction::ClearScreen),
+            "exit" => self.emit(Instruction::Exit),
+            "hires" => self.emit(Instruction::HighRes),
+            "lores" => self.emit(Instruction::LowRes),
+            "scroll-left" => self.emit(Instruction::ScrollLeft),
+            "scroll-right" => self.emit(Instruction::ScrollRight),
+            "scroll-down" => {
+                let rows = self.nibble()?;
+                self.emit(Instruction::ScrollDown { rows });
+            },
+            "scroll-up" => {
+                let rows = self.nibble()?;
+                self.emit(Instruction::ScrollUp { rows });
+            },
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(Instruction::StoreBcd { x });
+            },
+            "save" | "load" => {
+                let x = self.register()?;
+                let instr = match self.peek_is("-") {
+                    true => {
+                        self.pos += 1;
+                        let y = self.register()?;
+                        match token.text {
+                            "save" => Instruction::StoreRange { x, y },
+                            _      => Instruction::LoadRange { x, y }
+                        }
+                    },
+                    false => match token.text {
+                        "save" => Instruction::StoreRegisters { x },
+                        _      => Instruction::LoadRegisters { x }
+                    }
+                };
+                self.emit(instr);
+            },
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::StoreFlags { x });
+            },
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::LoadFlags { x });
+            },
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let rows = self.nibble()?;
+                self.emit(Instruction::Draw { x, y, rows });
+            },
+            "plane" => {
+                let planes = self.nibble()?;
+                self.emit(Instruction::SelectPlanes { planes });
+            },
+            "pitch" | "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.emit(match token.text {
+                    "pitch" => Instruction::SetPitch { x },
+                    "delay" => Instruction::SetDelayTimer { x },
+                    _       => Instruction::SetSoundTimer { x }
+                });
+            },
+            "i" | "I" => self.i_statement()?,
+            "if" => self.if_statement()?,
+            _ => match self.parse_register(token.text) {
+                Some(x) => self.register_statement(x)?,
+                // Bare numbers are raw data
+                None if parse_number(token.text).is_some() => {
+                    self.pos -= 1;
+                    self.emit_value(FixupKind::Byte)?;
+                },
+                // Anything else is taken to be a subroutine call by label
+                None => {
+                    self.pos -= 1;
+                    self.emit_with_address(Instruction::Call { addr: 0 })?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn i_statement(&mut self) -> Result<(), AssemblerError> {
+        let op = self.next()?;
+        match op.text {
+            ":=" => {
+                if self.peek_is("long") {
+                    self.pos += 1;
+                    // The address is the operand word, filled in from the next token
+                    self.emit(Instruction::SetILong { addr: 0 });
+                    self.fill_value(self.output.len() - 2, FixupKind::Addr16)?;
+                } else if self.peek_is("hex") || self.peek_is("bighex") {
+                    let font = self.next()?;
+                    let x = self.register()?;
+                    self.emit(match font.text {
+                        "hex" => Instruction::SetIToFont { x },
+                        _     => Instruction::SetIToBigFont { x }
+                    });
+                } else {
+                    self.emit_with_address(Instruction::SetI { addr: 0 })?;
+                }
+            },
+            "+=" => {
+                let x = self.register()?;
+                self.emit(Instruction::AddToI { x });
+            },
+            _ => return Err(self.error_at(op, format!("Expected ':=' or '+=' after i, found '{}'", op.text)))
+        }
+
+        Ok(())
+    }
+
+    fn register_statement(&mut self, x: u8) -> Result<(), AssemblerError> {
+        let op = self.next()?;
+        let operand = self.next()?;
+        let y = self.parse_register(operand.text);
+
+        let instr = match (op.text, y) {
+            (":=", Some(y))  => Instruction::Copy { x, y },
+            ("+=", Some(y))  => Instruction::Add { x, y },
+            ("-=", Some(y))  => Instruction::Subtract { x, y },
+            ("=-", Some(y))  => Instruction::SubtractReverse { x, y },
+            ("|=", Some(y))  => Instruction::Or { x, y },
+            ("&=", Some(y))  => Instruction::And { x, y },
+            ("^=", Some(y))  => Instruction::Xor { x, y },
+            (">>=", Some(y)) => Instruction::ShiftRight { x, y },
+            ("<<=", Some(y)) => Instruction::ShiftLeft { x, y },
+            (":=", None) => match operand.text {
+                "random" => {
+                    let mask = self.byte()?;
+                    Instruction::Random { x, mask }
+                },
+                "delay" => Instruction::GetDelayTimer { x },
+                "key"   => Instruction::AwaitKey { x },
+                _       => Instruction::SetRegister { x, value: self.get_byte(operand)? }
+            },
+            ("+=", None) => Instruction::AddValue { x, value: self.get_byte(operand)? },
+            ("-=", None) => Instruction::AddValue { x, value: self.get_byte(operand)?.wrapping_neg() },
+            _ => return Err(self.error_at(op, format!("Unknown register operation '{} {}'", op.text, operand.text)))
+        };
+
+        self.emit(instr);
+        Ok(())
+    }
+
+    fn if_statement(&mut self) -> Result<(), AssemblerError> {
+        // `if condition then statement` skips the statement when the condition is false, so each
+        // condition is assembled as the skip instruction for its opposite
+        let x = self.register()?;
+        let op = self.next()?;
+
+        let instr = match op.text {
+            "key"  => Instruction::SkipIfKeyNotPressed { x },
+            "-key" => Instruction::SkipIfKeyPressed { x },
+            "==" | "!=" => {
+                let operand = self.next()?;
+                match (op.text, self.parse_register(operand.text)) {
+                    ("==", Some(y)) => Instruction::SkipIfRegistersNotEqual { x, y },
+                    ("!=", Some(y)) => Instruction::SkipIfRegistersEqual { x, y },
+                    ("==", None)    => Instruction::SkipIfNotEqual { x, value: self.get_byte(operand)? },
+                    _               => Instruction::SkipIfEqual { x, value: self.get_byte(operand)? }
+                }
+            },
+            _ => return Err(self.error_at(op, format!("Unknown condition '{}'", op.text)))
+        };
+
+        self.expect("then")?;
+        self.emit(instr);
+        Ok(())
+    }
+
+    fn emit(&mut self, instr: Instruction) {
+        for word in std::iter::once(instr.encode()).chain(instr.encode_operand()) {
+            self.output.push((word >> 8) as u8);
+            self.output.push((word & 0xFF) as u8);
+        }
+    }
+
+    /**
+     * Emit an instruction whose low 12 bits are the address given by the next token
+     */
+    fn emit_with_address(&mut self, instr: Instruction) -> Result<(), AssemblerError> {
+        self.emit(instr);
+        self.fill_value(self.output.len() - 2, FixupKind::Addr12)
+    }
+
+    /**
+     * Emit the value of the next token as raw data
+     */
+    fn emit_value(&mut self, kind: FixupKind) -> Result<(), AssemblerError> {
+        let offset = self.output.len();
+        let size = match kind {
+            FixupKind::Byte => 1,
+            _               => 2
+        };
+        self.output.resize(offset + size, 0);
+        self.fill_value(offset, kind)
+    }
+
+    /**
+     * Fill in the value at offset from the next token, or once the label it names is defined
+     */
+    fn fill_value(&mut self, offset: usize, kind: FixupKind) -> Result<(), AssemblerError> {
+        let token = self.next()?;
+        match self.resolve(token)? {
+            Some(value) => self.patch(offset, kind, value, token.text, token.line)?,
+            None => self.fixups.push(Fixup { offset, kind, label: token.text.to_string(), line: token.line })
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Fill in a value which was left blank, checking it fits. Name is what the value was written as
+     * in the source, for error messages
+     */
+    fn patch(&mut self, offset: usize, kind: FixupKind, value: u16, name: &str, line: usize) -> Result<(), AssemblerError> {
+        let message = match kind {
+            FixupKind::Addr12 if value > 0xFFF => Some(format!("Address 0x{:04X} does not fit in 12 bits", value)),
+            // Negative numbers are allowed as bytes, as their two's complement
+            FixupKind::Byte if value > 0xFF && value < 0xFF80 => Some(format!("Value '{}' does not fit in a byte", name)),
+            _ => None
+        };
+        if let Some(message) = message {
+            return Err(AssemblerError { line, message });
+        }
+
+        match kind {
+            FixupKind::Addr12 => {
+                self.output[offset] = (self.output[offset] & 0xF0) | ((value >> 8) & 0xF) as u8;
+                self.output[offset + 1] = (value & 0xFF) as u8;
+            },
+            FixupKind::Addr16 => {
+                self.output[offset] = (value >> 8) as u8;
+                self.output[offset + 1] = (value & 0xFF) as u8;
+            },
+            FixupKind::Byte => self.output[offset] = (value & 0xFF) as u8
+        }
+
+        Ok(())
+    }
+
+    fn resolve_fixups(&mut self) -> Result<(), AssemblerError> {
+        let fixups: Vec<Fixup> = self.fixups.drain(..).collect();
+        for fixup in fixups {
+            match self.labels.get(&fixup.label) {
+                Some(&addr) => self.patch(fixup.offset, fixup.kind, addr, &fixup.label, fixup.line)?,
+                None => return Err(AssemblerError { line: fixup.line, message: format!("Undefined label '{}'", fixup.label) })
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Resolve a number, constant or already defined label. Returns None for names which aren't known
+     * yet, which are assumed to be labels defined later on
+     */
+    fn resolve(&self, token: Token) -> Result<Option<u16>, AssemblerError> {
+        if let Some(value) = parse_number(token.text) {
+            return Ok(Some(value));
+        }
+
+        if let Some(&value) = self.constants.get(token.text).or_else(|| self.labels.get(token.text)) {
+            return Ok(Some(value));
+        }
+
+        match token.text.chars().next() {
+            Some(c) if c.is_alphabetic() || c == '_' => Ok(None),
+            _ => Err(self.error_at(token, format!("Invalid value '{}'", token.text)))
+        }
+    }
+
+    fn get_constant(&self, token: Token) -> Result<u16, AssemblerError> {
+        match self.resolve(token)? {
+            Some(value) => Ok(value),
+            None => Err(self.error_at(token, format!("Undefined constant '{}'", token.text)))
+        }
+    }
+
+    fn get_byte(&self, token: Token) -> Result<u8, AssemblerError> {
+        let value = self.get_constant(token)?;
+        match value <= 0xFF || value >= 0xFF80 {
+            true  => Ok((value & 0xFF) as u8),
+            false => Err(self.error_at(token, format!("Value '{}' does not fit in a byte", token.text)))
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, AssemblerError> {
+        let token = self.next()?;
+        self.get_byte(token)
+    }
+
+    fn nibble(&mut self) -> Result<u8, AssemblerError> {
+        let token = self.next()?;
+        match self.get_constant(token)? {
+            value @ 0..=0xF => Ok(value as u8),
+            _ => Err(self.error_at(token, format!("Value '{}' does not fit in 4 bits", token.text)))
+        }
+    }
+
+    fn register(&mut self) -> Result<u8, AssemblerError> {
+        let token = self.next()?;
+        match self.parse_register(token.text) {
+            Some(register) => Ok(register),
+            None => Err(self.error_at(token, format!("Expected a register, found '{}'", token.text)))
+        }
+    }
+
+    fn parse_register(&self, text: &str) -> Option<u8> {
+        if let Some(&register) = self.aliases.get(text) {
+            return Some(register);
+        }
+
+        let mut chars = text.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some('v') | Some('V'), Some(digit), None) => digit.to_digit(16).map(|d| d as u8),
+            _ => None
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), AssemblerError> {
+        let token = self.next()?;
+        match token.text == text {
+            true  => Ok(()),
+            false => Err(self.error_at(token, format!("Expected '{}', found '{}'", text, token.text)))
+        }
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens.get(self.pos).map(|token| token.text == text).unwrap_or(false)
+    }
+
+    fn next(&mut self) -> Result<Token<'a>, AssemblerError> {
+        match self.tokens.get(self.pos) {
+            Some(&token) => {
+                self.pos += 1;
+                Ok(token)
+            },
+            None => {
+                let line = self.tokens.last().map(|token| token.line).unwrap_or(1);
+                Err(AssemblerError { line, message: "Unexpected end of file".to_string() })
+            }
+        }
+    }
+
+    /**
+     * The address the next byte will be assembled at. token is what needs the address, for the error
+     * if the program has run past the end of memory
+     */
+    fn get_address(&self, token: Token) -> Result<u16, AssemblerError> {
+        u16::try_from(self.output.len()).ok()
+            .and_then(|size| PROGRAM_START.checked_add(size))
+            .ok_or_else(|| self.error_at(token, "Program too large - it runs past the end of memory".to_string()))
+    }
+
+    fn error_at(&self, token: Token, message: String) -> AssemblerError {
+        AssemblerError { line: token.line, message }
+    }
+}
+
+/**
+ * Parse a decimal, hex (0x) or binary (0b) number. Negative numbers are returned as their two's
+ * complement so they can be used as bytes
+ */
+fn parse_number(text: &str) -> Option<u16> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text)
+    };
+
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        u16::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        u16::from_str_radix(bin, 2).ok()?
+    } else {
+        digits.parse::<u16>().ok()?
+    };
+
+    match negative {
+        true  => Some(value.wrapping_neg()),
+        false => Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assembles_instructions() {
+        assert_eq!(assemble("v0 := 0x12 i := 0x300 sprite v0 v1 5").unwrap(), vec![0x60, 0x12, 0xA3, 0x00, 0xD0, 0x15]);
+    }
+
+    #[test]
+    fn resolves_labels_defined_later() {
+        assert_eq!(assemble("jump end clear : end exit").unwrap(), vec![0x12, 0x04, 0x00, 0xE0, 0x00, 0xFD]);
+    }
+
+    #[test]
+    fn assembles_long_addresses_as_an_operand_word() {
+        let output = assemble("i := long data exit : data").unwrap();
+        assert_eq!(output, vec![0xF0, 0x00, 0x02, 0x06, 0x00, 0xFD]);
+        assert_eq!(Instruction::decode(0xF000, 0x0206), Some(Instruction::SetILong { addr: 0x0206 }));
+    }
+
+    #[test]
+    fn rejects_forward_addresses_over_12_bits() {
+        let error = assemble("jump far :org 0x1000 : far exit").unwrap_err();
+        assert_eq!(error.line, 1);
+    }
+
+    #[test]
+    fn rejects_bytes_too_large() {
+        assert!(assemble(":byte 0x1FF").is_err());
+        assert!(assemble(":byte big :const big 0x1FF").is_err());
+        assert_eq!(assemble(":byte 0xFF :byte -1").unwrap(), vec![0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn rejects_programs_past_the_end_of_memory() {
+        let error = assemble(":org 0xFFFE exit : end").unwrap_err();
+        assert!(error.message.contains("too large"));
+        assert!(assemble(":org 0xFFFE exit").is_ok());
+        assert!(assemble(":org 0xFFFE exit exit").is_err());
+    }
+
+    #[test]
+    fn starts_at_main() {
+        let source = "
+            : draw
+                sprite v0 v0 1
+                return
+            : main
+                draw
+        ";
+        assert_eq!(assemble(source).unwrap(), vec![0x12, 0x06, 0xD0, 0x01, 0x00, 0xEE, 0x22, 0x02]);
+        assert_eq!(assemble(": main clear").unwrap(), vec![0x00, 0xE0]);
+    }
+}
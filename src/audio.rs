@@ -0,0 +1,75 @@
+use std::f32::consts::PI;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+    Sawtooth,
+}
+
+impl Waveform {
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        match name.to_lowercase().as_str() {
+            "square"   => Some(Waveform::Square),
+            "sine"     => Some(Waveform::Sine),
+            "triangle" => Some(Waveform::Triangle),
+            "sawtooth" => Some(Waveform::Sawtooth),
+            _          => None
+        }
+    }
+
+    /**
+     * Value of the wave at a point in its cycle, where phase runs from 0 to 1
+     */
+    fn get_sample(&self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square   => match phase < 0.5 {
+                true  => 1.0,
+                false => -1.0
+            },
+            Waveform::Sine     => (phase * 2.0 * PI).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+        }
+    }
+}
+
+/**
+ * How the buzzer sounds
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioSettings {
+    pub frequency: f32, // Hz
+    pub volume: f32,    // 0.0 (silent) to 1.0
+    pub waveform: Waveform,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self { frequency: 440.0, volume: 0.25, waveform: Waveform::Square }
+    }
+}
+
+/**
+ * Generates the buzzer's tone as a stream of samples
+ */
+#[derive(Debug, Clone)]
+pub struct Oscillator {
+    settings: AudioSettings,
+    phase: f32,      // Position in the current cycle, from 0 to 1
+    phase_step: f32, // How far each sample moves through the cycle
+}
+
+impl Oscillator {
+    pub fn new(settings: AudioSettings, sample_rate: u32) -> Self {
+        Self { settings, phase: 0.0, phase_step: settings.frequency / sample_rate as f32 }
+    }
+
+    pub fn fill(&mut self, samples: &mut [f32]) {
+        for sample in samples.iter_mut() {
+            *sample = self.settings.waveform.get_sample(self.phase) * self.settings.volume;
+            self.phase = (self.phase + self.phase_step) % 1.0;
+        }
+    }
+}
@@ -1,102 +1,601 @@
-use std::collections::HashMap;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::WindowCanvas;
-use sdl2::Sdl;
-use crate::{display::Display, memory::Memory, rom::Rom, cpu::Cpu, keyboard::Keyboard};
-use crate::util::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use std::{fs, io};
+use crate::{debugger::Debugger, display::Display, frontend::{AudioSink, Clock, InputEvent, InputSource, VideoSink}, gdb::GdbStub, error::EmulatorError, memory::Memory, rom::Rom, cpu::Cpu, keyboard::Keyboard, movie::{Movie, MovieError, MoviePlayer, MovieRecorder}, quirks::Quirks, rewind::RewindBuffer, rng::Rng, savestate::{StateReader, StateWriter}, palette::Palette, screenshot::{save_screenshot, ImageFormat}, util::HIRES_DISPLAY_WIDTH, video::{VideoFormat, VideoRecorder}, timing::{TimingMode, VIP_INSTRUCTION_CYCLES_PER_FRAME}, variant::Variant};
+
+/**
+ * Point at which to stop emulation, checked around every instruction
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopCondition {
+    Address(u16), // Stop before executing the instruction at this address
+    Opcode(u16),  // Stop after executing this opcode
+}
+
+// Timers count down, and frames are run, at 60 Hz
+pub const FRAMES_PER_SECOND: u32 = 60;
+
+// Roughly the speed most CHIP-8 games expect
+pub const DEFAULT_INSTRUCTIONS_PER_SECOND: u32 = 600;
+
+// Five seconds of history
+const DEFAULT_REWIND_FRAMES: usize = 5 * FRAMES_PER_SECOND as usize;
 
 #[derive(Debug)]
 pub struct Chip8 {
-    cpu: Cpu
+    cpu: Cpu,
+    debugger: Option<Debugger>, // Only created once debugging is requested, so normal runs aren't slowed down
+    gdb: Option<GdbStub>,
+    rom_file: String, // Save state slots are stored alongside the ROM
+    rewind: RewindBuffer,
+    rewinding: bool, // Set while the rewind key is held
+    seed: u64,
+    rom_hash: u64,
+    frame: u64, // Frames run since the program started, used to time movie events
+    recorder: Option<MovieRecorder>,
+    player: Option<MoviePlayer>,
+    loop_playback: bool,
+    playback_start: Vec<u8>, // State to return to when a looping movie restarts
+    stop_condition: Option<StopCondition>,
+    stopped: bool, // Set once the stop condition has been met
+    muted: bool,
+    instructions_per_second: u32,
+    instruction_remainder: u32, // Instructions owed from earlier frames when the rate isn't a multiple of 60
+    timing: TimingMode,
+    video_recorder: Option<VideoRecorder>,
+    cycle_debt: u32, // VIP machine cycles the last frame's final instruction ran into this frame
+    messages: Vec<String>, // Status messages waiting for the frontend to show them
 }
 
 impl Chip8 {
 
-    pub fn new() -> Self {
-        let memory = Memory::new();
+    /**
+     * Create an emulator whose random numbers are generated from seed
+     */
+    pub fn new(variant: Variant, quirks: Quirks, seed: u64) -> Self {
+        let memory = Memory::new(variant);
         let display = Display::new();
         let keyboard = Keyboard::new();
-        Self { cpu: Cpu::new(memory, display, keyboard) }
+        Self {
+            cpu: Cpu::new(memory, display, keyboard, Rng::new(seed), variant, quirks),
+            debugger: None,
+            gdb: None,
+            rom_file: String::new(),
+            rewind: RewindBuffer::new(DEFAULT_REWIND_FRAMES),
+            rewinding: false,
+            seed,
+            rom_hash: 0,
+            frame: 0,
+            recorder: None,
+            player: None,
+            loop_playback: false,
+            playback_start: Vec::new(),
+            stop_condition: None,
+            stopped: false,
+            muted: false,
+            instructions_per_second: DEFAULT_INSTRUCTIONS_PER_SECOND,
+            instruction_remainder: 0,
+            timing: TimingMode::Fixed,
+            video_recorder: None,
+            cycle_debt: 0,
+            messages: Vec::new()
+        }
     }
 
-    pub fn run(&mut self, sdl: &Sdl, canvas: &mut WindowCanvas) {
-        println!("RUNNING CHIP8 PROGRAM...");
-        let mut event_pump = sdl.event_pump().unwrap();
-        let key_map = self.get_key_map();
+    /**
+     * Set how fast the CPU runs. Timers always count down at 60 Hz, whatever the speed
+     */
+    pub fn set_instructions_per_second(&mut self, instructions_per_second: u32) {
+        self.instructions_per_second = instructions_per_second;
+        self.instruction_remainder = 0;
+    }
 
-        'running: loop {
-            self.cpu.decrement_timer();
+    /**
+     * Choose between a fixed instruction rate and the COSMAC VIP's real instruction timings. The
+     * instruction rate is ignored with VIP timing
+     */
+    pub fn set_timing(&mut self, timing: TimingMode) {
+        self.timing = timing;
+        self.cycle_debt = 0;
+    }
 
-            for event in event_pump.poll_iter() {
-                match event {
-                    Event::Quit { .. } |
-                    Event::KeyDown { keycode: Some(Keycode::Escape), .. } => { break 'running; },
-                    Event::KeyDown { keycode, .. } => {
-                        if let Some(key) = key_map.get(&keycode.unwrap_or(Keycode::Ampersand)) {
-                            self.cpu.press_key(*key)
-                        }
-                    }
-                    Event::KeyUp { keycode, .. } => {
-                        if let Some(key) = key_map.get(&keycode.unwrap_or(Keycode::Ampersand)) {
-                            self.cpu.release_key(*key);
-                        }
-                    },
-                    _ => {}
-                }
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    /**
+     * Set how many frames of history are kept for rewinding
+     */
+    pub fn set_rewind_length(&mut self, frames: usize) {
+        self.rewind = RewindBuffer::new(frames);
+    }
+
+    /**
+     * Start the program paused in the debugger
+     */
+    pub fn enable_debugger(&mut self) {
+        self.debugger.get_or_insert_with(Default::default).pause();
+    }
+
+    /**
+     * Wait for a GDB client to attach on the given port, and start the program stopped under its control
+     */
+    pub fn attach_gdb(&mut self, port: u16) -> io::Result<()> {
+        self.gdb = Some(GdbStub::listen(port)?);
+        Ok(())
+    }
+
+    /**
+     * Stop running frames once the condition is met - see `has_stopped`
+     */
+    pub fn set_stop_condition(&mut self, condition: StopCondition) {
+        self.stop_condition = Some(condition);
+    }
+
+    pub fn has_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /**
+     * Record key presses from now until the emulator stops, then write them to a movie file
+     */
+    pub fn start_recording(&mut self, file: &str) {
+        let movie = Movie::new(self.rom_hash, self.cpu.get_variant(), self.cpu.get_quirks(), self.seed, self.instructions_per_second, self.timing);
+        self.recorder = Some(MovieRecorder::new(movie, file));
+    }
+
+    /**
+     * Replace keyboard input with the key presses from a movie. The emulator must have been created
+     * with the movie's variant, quirks and seed, be set to its speed and timing, and have its ROM loaded
+     */
+    pub fn start_playback(&mut self, movie: Movie, looping: bool) -> Result<(), MovieError> {
+        if movie.rom_hash != self.rom_hash {
+            return Err(MovieError::Mismatch { reason: "it was recorded with a different ROM" });
+        }
+        if movie.seed != self.seed || movie.variant != self.cpu.get_variant() || movie.quirks != self.cpu.get_quirks()
+            || movie.instructions_per_second != self.instructions_per_second || movie.timing != self.timing {
+            return Err(MovieError::Mismatch { reason: "it was recorded with different settings" });
+        }
+
+        self.playback_start = self.save_state();
+        self.frame = 0;
+        self.instruction_remainder = 0;
+        self.cycle_debt = 0;
+        self.report(format!("Playing movie ({} frames)", movie.length));
+        self.player = Some(MoviePlayer::new(movie));
+        self.loop_playback = looping;
+        Ok(())
+    }
+
+    /**
+     * Run frames until the program exits or the frontend asks to quit. Status messages are shown by
+     * the frontend while running, and any left at the end, e.g. from saving a video, are kept for
+     * `take_messages`
+     */
+    pub fn run(&mut self, video: &mut dyn VideoSink, input: &mut dyn InputSource, audio: &mut dyn AudioSink, clock: &mut dyn Clock) -> Result<(), EmulatorError> {
+        self.report("RUNNING CHIP8 PROGRAM...".to_string());
+        let result = self.run_frames(video, input, audio, clock);
+
+        // Keep whatever was recorded, even if the program crashed - that's often the interesting part
+        self.finish_recording();
+        self.stop_video();
+
+        result
+    }
+
+    /**
+     * Status messages, e.g. from saving a state, since the last call. Frontends which don't go
+     * through `run` should show these after each frame
+     */
+    pub fn take_messages(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.messages)
+    }
+
+    fn report(&mut self, message: String) {
+        self.messages.push(message);
+    }
+
+    /**
+     * Write out the movie being recorded, if there is one. `run` does this itself when it finishes
+     */
+    pub fn finish_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            let file = recorder.get_file().to_string();
+            match recorder.finish(self.frame) {
+                Ok(()) => self.report(format!("Saved movie {} ({} frames)", file, self.frame)),
+                Err(e) => self.report(format!("Failed to save movie: {}", e))
+            }
+        }
+    }
+
+    /**
+     * Record every frame from now on to a GIF or Y4M video, until `stop_video` is called or `run`
+     * finishes. scale is the size of a high resolution pixel in the video
+     */
+    pub fn start_video(&mut self, file: &str, format: VideoFormat, scale: usize, palette: Palette) -> io::Result<()> {
+        self.stop_video();
+        self.video_recorder = Some(VideoRecorder::new(file, format, scale, palette)?);
+        self.report(format!("Recording video to {}", file));
+        Ok(())
+    }
+
+    pub fn stop_video(&mut self) {
+        if let Some(recorder) = self.video_recorder.take() {
+            let frames = recorder.get_frames();
+            match recorder.finish() {
+                Ok(()) => self.report(format!("Saved video ({} frames)", frames)),
+                Err(e) => self.report(format!("Failed to save video: {}", e))
+            }
+        }
+    }
+
+    /**
+     * Handle a frame's input and run one frame's worth of instructions. Returns false once the
+     * emulator should stop
+     */
+    pub fn run_frame(&mut self, events: &[InputEvent]) -> Result<bool, EmulatorError> {
+        self.cpu.decrement_timers();
+        self.cpu.vblank();
+
+        for event in events {
+            match *event {
+                InputEvent::Quit            => return Ok(false),
+                InputEvent::KeyDown(key)    => self.press_key(key)?,
+                InputEvent::KeyUp(key)      => self.release_key(key)?,
+                InputEvent::SaveState(slot) => self.save_slot(slot),
+                InputEvent::LoadState(slot) => self.load_slot(slot),
+                // Rewinding would desync a movie from the frames it's recorded against
+                InputEvent::RewindStart     => self.rewinding = !self.is_movie_active(),
+                InputEvent::RewindStop      => self.rewinding = false,
+                InputEvent::Debug           => self.enable_debugger(),
+                InputEvent::ToggleMute      => self.muted = !self.muted,
+                // Screenshots and videos need the frontend's colours and scale, so are handled in `run`
+                InputEvent::Screenshot |
+                InputEvent::ToggleVideo     => (),
             }
+        }
 
-            // Tick CPU 10 times per frame
-            for _ in 0..10 {
-                self.cpu.execute();
+        // Step backwards a frame at a time while rewinding, staying on the oldest frame once history runs out
+        if self.rewinding {
+            if let Some(state) = self.rewind.pop() {
+                self.load_state(&state)?;
             }
+            self.record_video_frame();
+            return Ok(true);
+        }
 
-            self.draw_screen(canvas);
+        self.play_movie_frame()?;
+
+        let running = match self.timing {
+            TimingMode::Fixed     => self.run_fixed_instructions()?,
+            TimingMode::CosmacVip => self.run_vip_instructions()?
+        };
+
+        // SUPER-CHIP programs can exit the interpreter with 00FD
+        if self.cpu.has_exited() {
+            if let Some(gdb) = self.gdb.as_mut() {
+                gdb.exited();
+            }
+            return Ok(false);
+        }
+        if !running {
+            return Ok(false);
         }
+
+        self.frame += 1;
+        self.rewind.push(self.save_state());
+        self.record_video_frame();
+        Ok(true)
     }
 
-    pub fn load_rom(&mut self, file: &str) {
-        let rom = Rom::new(file);
-        self.cpu.load_program(rom);
+    /**
+     * Execute a single instruction, unless the debugger, GDB or the stop condition stops it first.
+     * Timers aren't counted down - that happens once per frame in `run_frame`. Returns false once
+     * the emulator should stop
+     */
+    pub fn step(&mut self) -> Result<bool, EmulatorError> {
+        if let Some(debugger) = self.debugger.as_mut() {
+            if debugger.should_break(&self.cpu) && !debugger.prompt(&mut self.cpu) {
+                return Ok(false);
+            }
+        }
+        if let Some(gdb) = self.gdb.as_mut() {
+            if gdb.should_break(&self.cpu) && !gdb.serve(&mut self.cpu) {
+                return Ok(false);
+            }
+        }
+
+        if self.stop_condition == Some(StopCondition::Address(self.cpu.get_program_counter())) {
+            self.stopped = true;
+            return Ok(false);
+        }
+
+        // Nothing is executed while waiting for vblank
+        let opcode = match self.cpu.is_waiting_for_vblank() {
+            true  => None,
+            false => self.cpu.get_next_opcode()
+        };
+        self.cpu.execute()?;
+
+        if let (Some(StopCondition::Opcode(stop_opcode)), Some(opcode)) = (self.stop_condition, opcode) {
+            if opcode == stop_opcode {
+                self.stopped = true;
+                return Ok(false);
+            }
+        }
+        Ok(!self.cpu.has_exited())
+    }
+
+    pub fn get_display(&self) -> &Display {
+        self.cpu.get_display()
     }
 
-    fn get_key_map(&self) -> HashMap<Keycode, u8> {
-        let mut key_map = HashMap::new();
-        key_map.insert(Keycode::X, 0x0);
-        key_map.insert(Keycode::Num1, 0x1);
-        key_map.insert(Keycode::Num2, 0x2);
-        key_map.insert(Keycode::Num3, 0x3);
-        key_map.insert(Keycode::Q, 0x4);
-        key_map.insert(Keycode::W, 0x5);
-        key_map.insert(Keycode::E, 0x6);
-        key_map.insert(Keycode::A, 0x7);
-        key_map.insert(Keycode::S, 0x8);
-        key_map.insert(Keycode::D, 0x9);
-        key_map.insert(Keycode::Z, 0xA);
-        key_map.insert(Keycode::C, 0xB);
-        key_map.insert(Keycode::Num4, 0xC);
-        key_map.insert(Keycode::R, 0xD);
-        key_map.insert(Keycode::F, 0xE);
-        key_map.insert(Keycode::V, 0xF);
-        key_map
+    pub fn get_cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    /**
+     * The pixels currently on screen, a row at a time from the top left. Each is a colour index
+     * from 0 to 3 - anything other than 0 is lit outside of XO-CHIP
+     */
+    pub fn get_framebuffer(&self) -> Vec<u8> {
+        let display = self.cpu.get_display();
+        let mut pixels = Vec::with_capacity(display.get_width() * display.get_height());
+        for y in 0..display.get_height() {
+            for x in 0..display.get_width() {
+                pixels.push(display.get_pixel(x, y));
+            }
+        }
+        pixels
     }
 
-    fn draw_screen(&self, canvas: &mut WindowCanvas) {
-        canvas.set_draw_color(Color::RGB(0, 0, 0));
-        canvas.clear();
-        canvas.set_draw_color(Color::RGB(255, 255, 255));
+    /**
+     * Press or release one of the CHIP-8 keys 0-F, as a KeyDown or KeyUp event would. Any other
+     * key is an InvalidKey error
+     */
+    pub fn set_key(&mut self, key: u8, pressed: bool) -> Result<(), EmulatorError> {
+        match pressed {
+            true  => self.press_key(key),
+            false => self.release_key(key)
+        }
+    }
+
+    /**
+     * Run a frame's share of the instructions per second, spread evenly over the frames in each second
+     */
+    fn run_fixed_instructions(&mut self) -> Result<bool, EmulatorError> {
+        let instructions = self.instructions_per_second + self.instruction_remainder;
+        self.instruction_remainder = instructions % FRAMES_PER_SECOND;
+
+        for _ in 0..instructions / FRAMES_PER_SECOND {
+            if !self.step()? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /**
+     * Run instructions until the frame's machine cycles are used up, as the VIP would between
+     * interrupts. An instruction which runs past the interrupt delays the next frame's instructions,
+     * and waiting for vblank gives up the rest of the frame
+     */
+    fn run_vip_instructions(&mut self) -> Result<bool, EmulatorError> {
+        let mut cycles = self.cycle_debt;
+        while cycles < VIP_INSTRUCTION_CYCLES_PER_FRAME {
+            if !self.step()? {
+                return Ok(false);
+            }
+            if self.cpu.is_waiting_for_vblank() {
+                cycles = VIP_INSTRUCTION_CYCLES_PER_FRAME;
+                break;
+            }
+            cycles += self.cpu.get_last_cycles();
+        }
+
+        self.cycle_debt = cycles - VIP_INSTRUCTION_CYCLES_PER_FRAME;
+        Ok(true)
+    }
+
+    fn run_frames(&mut self, video: &mut dyn VideoSink, input: &mut dyn InputSource, audio: &mut dyn AudioSink, clock: &mut dyn Clock) -> Result<(), EmulatorError> {
+        loop {
+            let events = input.poll_events();
+            if !self.run_frame(&events)? {
+                return Ok(());
+            }
+
+            // The buzzer sounds for as long as the sound timer is running
+            audio.set_playing(self.cpu.get_sound_timer() > 0 && !self.muted);
+            for message in self.take_messages() {
+                video.show_message(&message);
+            }
+            video.present(self.cpu.get_display());
+            if events.contains(&InputEvent::Screenshot) {
+                self.save_screenshot(video);
+            }
+            if events.contains(&InputEvent::ToggleVideo) {
+                self.toggle_video(video);
+            }
+            clock.wait_for_frame();
+        }
+    }
+
+    /**
+     * Load a ROM image, or assemble and load an Octo style source file if the file ends in .8o
+     */
+    pub fn load_rom(&mut self, file: &str) -> Result<(), EmulatorError> {
+        let rom = Rom::load(file)?;
+        self.report(format!("Loaded ROM {}", file));
+        self.rom_file = file.to_string();
+        self.load_program(rom)
+    }
+
+    /**
+     * Load a program which is already in memory rather than in a file
+     */
+    pub fn load_rom_bytes(&mut self, data: &[u8]) -> Result<(), EmulatorError> {
+        self.load_program(Rom::from_bytes(data.to_vec()))
+    }
+
+    /**
+     * Capture the whole machine in the format described in savestate.rs
+     */
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        self.cpu.save_state(&mut writer);
+        writer.finish()
+    }
+
+    /**
+     * Restore the machine from a save state. The machine is left untouched if the state is invalid
+     */
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), EmulatorError> {
+        let mut reader = StateReader::new(data)?;
+        let mut cpu = self.cpu.clone();
+        cpu.load_state(&mut reader)?;
+        reader.finish()?;
+        self.cpu = cpu;
+        Ok(())
+    }
+
+    fn load_program(&mut self, rom: Rom) -> Result<(), EmulatorError> {
+        self.rom_hash = rom.get_hash();
+        self.cpu.load_program(rom.get_bytes())
+    }
+
+    fn get_slot_file(&self, slot: u8) -> String {
+        format!("{}.state{}", self.rom_file, slot)
+    }
+
+    /**
+     * Save the screen as the frontend shows it, alongside the ROM and named after the frame
+     */
+    fn save_screenshot(&mut self, video: &dyn VideoSink) {
+        let display = self.cpu.get_display();
+        let file = format!("{}.{}.png", self.rom_file, self.frame);
+        match save_screenshot(display, &file, ImageFormat::Png, video.get_scale(display), &video.get_palette()) {
+            Ok(()) => self.report(format!("Saved screenshot to {}", file)),
+            Err(e) => self.report(format!("Failed to save screenshot to {}: {}", file, e))
+        }
+    }
+
+    /**
+     * Start recording a GIF alongside the ROM at the frontend's scale, or stop the current recording
+     */
+    fn toggle_video(&mut self, video: &dyn VideoSink) {
+        if self.video_recorder.is_some() {
+            self.stop_video();
+            return;
+        }
+
+        let display = self.cpu.get_display();
+        let scale = (video.get_scale(display) * display.get_width() / HIRES_DISPLAY_WIDTH).max(1);
+        let file = format!("{}.{}.gif", self.rom_file, self.frame);
+        if let Err(e) = self.start_video(&file, VideoFormat::Gif, scale, video.get_palette()) {
+            self.report(format!("Failed to record video to {}: {}", file, e));
+        }
+    }
+
+    fn record_video_frame(&mut self) {
+        let result = match self.video_recorder.as_mut() {
+            Some(recorder) => recorder.add_frame(self.cpu.get_display()),
+            None => return
+        };
+
+        if let Err(e) = result {
+            self.report(format!("Stopped recording video: {}", e));
+            self.video_recorder = None;
+        }
+    }
+
+    fn save_slot(&mut self, slot: u8) {
+        match fs::write(self.get_slot_file(slot), self.save_state()) {
+            Ok(()) => self.report(format!("Saved state to slot {}", slot)),
+            Err(e) => self.report(format!("Failed to save state to slot {}: {}", slot, e))
+        }
+    }
+
+    fn load_slot(&mut self, slot: u8) {
+        if self.is_movie_active() {
+            self.report("States can't be loaded while a movie is recording or playing".to_string());
+            return;
+        }
+
+        let result = fs::read(self.get_slot_file(slot))
+            .map_err(|e| e.to_string())
+            .and_then(|data| self.load_state(&data).map_err(|e| e.to_string()));
+
+        match result {
+            Ok(()) => self.report(format!("Loaded state from slot {}", slot)),
+            Err(e) => self.report(format!("Failed to load state from slot {}: {}", slot, e))
+        }
+    }
+
+    fn is_movie_active(&self) -> bool {
+        self.recorder.is_some() || self.player.is_some()
+    }
+
+    fn press_key(&mut self, key: u8) -> Result<(), EmulatorError> {
+        // Keyboard input is ignored while a movie is playing
+        if self.player.is_some() {
+            return Ok(());
+        }
+        self.cpu.press_key(key)?;
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(self.frame, key, true);
+        }
+        Ok(())
+    }
+
+    fn release_key(&mut self, key: u8) -> Result<(), EmulatorError> {
+        if self.player.is_some() {
+            return Ok(());
+        }
+        self.cpu.release_key(key)?;
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(self.frame, key, false);
+        }
+        Ok(())
+    }
+
+    /**
+     * Feed the movie's key presses for this frame to the CPU, restarting or ending playback once
+     * the movie is over
+     */
+    fn play_movie_frame(&mut self) -> Result<(), EmulatorError> {
+        let finished = match self.player.as_ref() {
+            Some(player) => player.is_finished(self.frame),
+            None => return Ok(())
+        };
+
+        if finished {
+            match self.loop_playback {
+                true  => {
+                    let start = std::mem::take(&mut self.playback_start);
+                    self.load_state(&start)?;
+                    self.playback_start = start;
+                    self.frame = 0;
+                    self.instruction_remainder = 0;
+                    self.cycle_debt = 0;
+                    if let Some(player) = self.player.as_mut() {
+                        player.restart();
+                    }
+                },
+                false => {
+                    self.report("Movie finished".to_string());
+                    self.player = None;
+                    return Ok(());
+                }
+            }
+        }
 
-        for y in 0..DISPLAY_HEIGHT {
-            for x in 0..DISPLAY_WIDTH {
-                if self.cpu.get_display_pixel(x, y) > 0 {
-                    let rect = Rect::new(x as i32, y as i32, 1, 1);
-                    canvas.fill_rect(rect).unwrap();
+        if let Some(player) = self.player.as_mut() {
+            for event in player.take_events(self.frame) {
+                match event.pressed {
+                    true  => self.cpu.press_key(event.key)?,
+                    false => self.cpu.release_key(event.key)?
                 }
             }
         }
 
-        canvas.present();
+        Ok(())
     }
 }
\ No newline at end of file
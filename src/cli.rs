@@ -0,0 +1,289 @@
+use crate::config::Config;
+use rusty_chip::{audio::{AudioSettings, Waveform}, chip8::{StopCondition, DEFAULT_INSTRUCTIONS_PER_SECOND}, headless::DumpFormat, keymap::KeyMap, palette::{parse_color, Palette}, quirks::Quirks, screenshot::ImageFormat, video::VideoFormat, timing::TimingMode, variant::Variant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Run,         // rusty-chip <rom> [options]
+    Disassemble, // rusty-chip disasm <rom> [options]
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub command: Command,
+    pub rom_file: String,
+    pub variant: Variant,
+    pub quirks: Quirks,
+    pub debug: bool, // Start paused in the debugger
+    pub gdb_port: Option<u16>, // Wait for GDB to attach on this port before running
+    pub rewind_seconds: usize, // How far back gameplay can be rewound - 0 disables rewinding
+    pub seed: Option<u64>, // Seed for the random number generator, chosen at random if not given
+    pub record_file: Option<String>, // Record key presses to this movie file
+    pub play_file: Option<String>, // Play back key presses from this movie file
+    pub loop_playback: bool, // Restart the movie from the beginning when it ends
+    pub headless: bool, // Run without a window and dump the final screen
+    pub tui: bool, // Draw in the terminal instead of a window
+    pub frames: Option<u64>, // Number of frames to run headless
+    pub stop_condition: Option<StopCondition>,
+    pub stable_frames: Option<u64>, // Stop headless runs once the screen is unchanged for this many frames
+    pub dump_format: DumpFormat,
+    pub dump_file: Option<String>,
+    pub screenshot_file: Option<String>, // Save the final screen of a headless run to this PNG or PBM file
+    pub scale: usize, // Size of each pixel in the screenshot, or each high resolution pixel in the video
+    pub video_file: Option<String>, // Record every frame to this GIF or Y4M file
+    pub palette: Palette, // Colours for the screen, screenshots and videos
+    pub key_map: KeyMap, // Host keys for each CHIP-8 key
+    pub tone_frequency: f32, // Pitch of the buzzer in Hz
+    pub volume: f32, // Buzzer volume from 0.0 to 1.0
+    pub waveform: Waveform,
+    pub mute: bool, // Start with the buzzer muted - F9 toggles it
+    pub instructions_per_second: u32,
+    pub timing: TimingMode, // Fixed instruction rate, or the COSMAC VIP's real instruction timings
+}
+
+impl Options {
+    /**
+     * Parse the command line (without the program name). Optional flags follow the ROM file,
+     * e.g. `rusty-chip game.ch8 --variant schip`. Settings from the config file are applied
+     * first, so the command line overrides them
+     */
+    pub fn parse(args: &[String]) -> Options {
+        let (command, args) = match args.first().map(|arg| arg.as_str()) {
+            Some("disasm") => (Command::Disassemble, &args[1..]),
+            _              => (Command::Run, args)
+        };
+
+        let rom_file = args.first().expect("A ROM file is required").clone();
+
+        let mut variant = Variant::Chip8;
+        let mut quirks = None;
+        let mut debug = false;
+        let mut gdb_port = None;
+        let mut rewind_seconds = 5;
+        let mut seed = None;
+        let mut record_file = None;
+        let mut play_file = None;
+        let mut loop_playback = false;
+        let mut headless = false;
+        let mut tui = false;
+        let mut frames = None;
+        let mut stop_condition = None;
+        let mut stable_frames = None;
+        let mut dump_format = DumpFormat::Ascii;
+        let mut dump_file = None;
+        let mut screenshot_file = None;
+        let mut scale = 1;
+        let mut video_file = None;
+        let mut palette = Palette::default();
+        let mut key_map = KeyMap::default();
+        let audio_defaults = AudioSettings::default();
+        let mut tone_frequency = audio_defaults.frequency;
+        let mut volume = audio_defaults.volume;
+        let mut waveform = audio_defaults.waveform;
+        let mut mute = false;
+        let mut instructions_per_second = DEFAULT_INSTRUCTIONS_PER_SECOND;
+        let mut timing = TimingMode::Fixed;
+
+        let config = load_config(args);
+        for (name, value) in config.get_settings(&rom_file) {
+            match name {
+                "theme"      => palette = Palette::from_name(value).expect("Unknown theme in config - expected one of classic, amber, green, lcd, high-contrast"),
+                "palette"    => palette = Palette::parse(value).expect("Invalid palette in config - expected four colours, e.g. #000000,#ffffff,#aaaaaa,#555555"),
+                "foreground" => palette.set_foreground(parse_color(value).expect("Invalid foreground in config - expected a colour, e.g. #ffffff")),
+                "background" => palette.set_background(parse_color(value).expect("Invalid background in config - expected a colour, e.g. #000000")),
+                "keys"       => key_map = KeyMap::from_name(value).expect("Unknown keys in config - expected one of qwerty, azerty, dvorak, numpad, vip"),
+                // key.5 = w, up
+                _            => match name.strip_prefix("key.").map(parse_key) {
+                    Some(key) => key_map.bind_list(key.expect("Invalid key in config - expected key.0 to key.f"), value),
+                    None      => panic!("Unknown setting {} in config", name)
+                }
+            }
+        }
+
+        let mut flags = args[1..].iter();
+        while let Some(flag) = flags.next() {
+            match flag.as_str() {
+                "--variant" => {
+                    let name = flags.next().expect("--variant requires a value");
+                    variant = Variant::from_name(name).expect("Unknown variant - expected one of chip8, schip, xochip");
+                },
+                "--quirks" => {
+                    let name = flags.next().expect("--quirks requires a value");
+                    quirks = Some(Quirks::from_name(name).expect("Unknown quirks - expected one of vip, chip48, schip, xochip"));
+                },
+                "--debug" => debug = true,
+                "--gdb" => {
+                    let port = flags.next().expect("--gdb requires a port");
+                    gdb_port = Some(port.parse().expect("Invalid port for --gdb"));
+                },
+                "--rewind" => {
+                    let seconds = flags.next().expect("--rewind requires a number of seconds");
+                    rewind_seconds = seconds.parse().expect("Invalid number of seconds for --rewind");
+                },
+                "--seed" => {
+                    let value = flags.next().expect("--seed requires a value");
+                    seed = Some(value.parse().expect("Invalid seed - expected a number"));
+                },
+                "--record" => record_file = Some(flags.next().expect("--record requires a file").clone()),
+                "--play" => play_file = Some(flags.next().expect("--play requires a file").clone()),
+                "--loop" => loop_playback = true,
+                "--headless" => headless = true,
+                "--tui" => tui = true,
+                "--frames" => {
+                    let value = flags.next().expect("--frames requires a number");
+                    frames = Some(value.parse().expect("Invalid number of frames"));
+                },
+                "--until-pc" => {
+                    let value = flags.next().expect("--until-pc requires an address");
+                    stop_condition = Some(StopCondition::Address(parse_hex(value).expect("Invalid address for --until-pc")));
+                },
+                "--until-opcode" => {
+                    let value = flags.next().expect("--until-opcode requires an opcode");
+                    stop_condition = Some(StopCondition::Opcode(parse_hex(value).expect("Invalid opcode for --until-opcode")));
+                },
+                "--until-stable" => {
+                    let value = flags.next().expect("--until-stable requires a number of frames");
+                    stable_frames = Some(value.parse().expect("Invalid number of frames for --until-stable"));
+                },
+                "--dump" => {
+                    let name = flags.next().expect("--dump requires a format");
+                    dump_format = DumpFormat::from_name(name).expect("Unknown dump format - expected one of ascii, pbm");
+                },
+                "--dump-file" => dump_file = Some(flags.next().expect("--dump-file requires a file").clone()),
+                "--screenshot" => {
+                    let file = flags.next().expect("--screenshot requires a file");
+                    ImageFormat::from_file(file).expect("Unknown image format - expected a .png or .pbm file");
+                    screenshot_file = Some(file.clone());
+                },
+                "--video" => {
+                    let file = flags.next().expect("--video requires a file");
+                    VideoFormat::from_file(file).expect("Unknown video format - expected a .gif or .y4m file");
+                    video_file = Some(file.clone());
+                },
+                "--scale" => {
+                    let value = flags.next().expect("--scale requires a number");
+                    scale = value.parse().ok().filter(|scale| *scale > 0).expect("Invalid scale - expected a whole number from 1");
+                },
+                "--config" => {
+                    flags.next();
+                },
+                "--theme" => {
+                    let name = flags.next().expect("--theme requires a value");
+                    palette = Palette::from_name(name).expect("Unknown theme - expected one of classic, amber, green, lcd, high-contrast");
+                },
+                "--palette" => {
+                    let value = flags.next().expect("--palette requires four colours");
+                    palette = Palette::parse(value).expect("Invalid palette - expected four colours, e.g. #000000,#ffffff,#aaaaaa,#555555");
+                },
+                "--fg" => {
+                    let value = flags.next().expect("--fg requires a colour");
+                    palette.set_foreground(parse_color(value).expect("Invalid colour for --fg - expected e.g. #ffffff"));
+                },
+                "--bg" => {
+                    let value = flags.next().expect("--bg requires a colour");
+                    palette.set_background(parse_color(value).expect("Invalid colour for --bg - expected e.g. #000000"));
+                },
+                "--keys" => {
+                    let name = flags.next().expect("--keys requires a value");
+                    key_map = KeyMap::from_name(name).expect("Unknown keys - expected one of qwerty, azerty, dvorak, numpad, vip");
+                },
+                "--tone" => {
+                    let value = flags.next().expect("--tone requires a frequency");
+                    tone_frequency = value.parse().expect("Invalid frequency for --tone");
+                },
+                "--volume" => {
+                    let value = flags.next().expect("--volume requires a value");
+                    volume = value.parse::<f32>().expect("Invalid volume - expected a number from 0 to 1").clamp(0.0, 1.0);
+                },
+                "--waveform" => {
+                    let name = flags.next().expect("--waveform requires a value");
+                    waveform = Waveform::from_name(name).expect("Unknown waveform - expected one of square, sine, triangle, sawtooth");
+                },
+                "--mute" => mute = true,
+                "--ips" => {
+                    let value = flags.next().expect("--ips requires a number of instructions per second");
+                    instructions_per_second = value.parse().expect("Invalid number of instructions per second");
+                },
+                "--timing" => {
+                    let name = flags.next().expect("--timing requires a value");
+                    timing = TimingMode::from_name(name).expect("Unknown timing - expected one of fixed, vip");
+                },
+                _ => panic!("Unknown option {}", flag)
+            }
+        }
+
+        // The debugger reads commands from the terminal, which the terminal frontend has taken over
+        if debug && tui {
+            panic!("--debug can't be used with --tui");
+        }
+
+        // Unless told otherwise, use the quirks of the interpreter the variant is usually played on
+        let quirks = quirks.unwrap_or_else(|| Quirks::for_variant(variant));
+
+        Options {
+            command,
+            rom_file,
+            variant,
+            quirks,
+            debug,
+            gdb_port,
+            rewind_seconds,
+            seed,
+            record_file,
+            play_file,
+            loop_playback,
+            headless,
+            tui,
+            frames,
+            stop_condition,
+            stable_frames,
+            dump_format,
+            dump_file,
+            screenshot_file,
+            scale,
+            video_file,
+            palette,
+            key_map,
+            tone_frequency,
+            volume,
+            waveform,
+            mute,
+            instructions_per_second,
+            timing
+        }
+    }
+}
+
+/**
+ * Read the file given with --config, or the default config file if there is one
+ */
+fn load_config(args: &[String]) -> Config {
+    let file = args.iter().position(|arg| arg == "--config").map(|index| {
+        args.get(index + 1).expect("--config requires a file").clone()
+    });
+
+    match file {
+        Some(file) => Config::load(&file).unwrap_or_else(|e| panic!("Failed to read config {}: {}", file, e)),
+        None       => match Config::get_default_file().filter(|file| file.exists()) {
+            Some(file) => Config::load(&file.to_string_lossy()).unwrap_or_else(|e| panic!("Failed to read config {}: {}", file.display(), e)),
+            None       => Config::default()
+        }
+    }
+}
+
+/**
+ * Parse a hex number, with or without a 0x prefix
+ */
+fn parse_hex(value: &str) -> Option<u16> {
+    let digits = value.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).ok()
+}
+
+/**
+ * Parse a single hex digit naming a CHIP-8 key
+ */
+fn parse_key(value: &str) -> Option<u8> {
+    match value.len() {
+        1 => u8::from_str_radix(value, 16).ok(),
+        _ => None
+    }
+}
@@ -0,0 +1,71 @@
+use std::{env, fs, path::{Path, PathBuf}};
+
+/**
+ * Settings read from a config file of `name = value` lines. Blank lines and lines starting with #
+ * are ignored. Settings after a `[file name]` line only apply to ROMs with that file name, e.g.
+ *
+ * ```text
+ * # Amber screen, but with pure black behind it
+ * theme = amber
+ * background = #000000
+ *
+ * [pong.ch8]
+ * key.1 = w, up
+ * key.4 = s, down
+ * ```
+ */
+#[derive(Debug, Default)]
+pub struct Config {
+    settings: Vec<(Option<String>, String, String)>, // ROM file name, if only for one ROM, then the name and value
+}
+
+impl Config {
+    pub fn load(file: &str) -> Result<Config, String> {
+        let text = fs::read_to_string(file).map_err(|e| e.to_string())?;
+        Config::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut settings = Vec::new();
+        let mut section = None;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(rom_name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                section = Some(rom_name.trim().to_lowercase());
+                continue;
+            }
+
+            match line.split_once('=') {
+                Some((name, value)) => settings.push((section.clone(), name.trim().to_lowercase(), value.trim().to_string())),
+                None                => return Err(format!("Expected name = value on line {}", number + 1))
+            }
+        }
+        Ok(Config { settings })
+    }
+
+    /**
+     * Settings which apply to a ROM, as (name, value). Those for every ROM come first, so the ROM's
+     * own settings override them
+     */
+    pub fn get_settings(&self, rom_file: &str) -> Vec<(&str, &str)> {
+        let rom_name = Path::new(rom_file).file_name().map(|name| name.to_string_lossy().to_lowercase());
+        let global = self.settings.iter().filter(|(section, _, _)| section.is_none());
+        let for_rom = self.settings.iter().filter(|(section, _, _)| section.is_some() && *section == rom_name);
+        global.chain(for_rom).map(|(_, name, value)| (name.as_str(), value.as_str())).collect()
+    }
+
+    /**
+     * Where the config is read from when --config isn't given -
+     * $XDG_CONFIG_HOME/rusty-chip/config, or ~/.config/rusty-chip/config
+     */
+    pub fn get_default_file() -> Option<PathBuf> {
+        let config_dir = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config_dir.join("rusty-chip").join("config"))
+    }
+}
@@ -1,22 +1,40 @@
-use rand::random;
-use crate::{display::Display, memory::Memory, rom::Rom, util::{get_bit_val, DISPLAY_WIDTH, DISPLAY_HEIGHT}, keyboard::Keyboard};
-
-#[derive(Debug)]
-pub struct Cpu {
+use crate::{display::Display, error::EmulatorError, instruction::Instruction, memory::Memory, util::{get_bit_val, BIG_FONT_START, MAX_MEMORY_SIZE, STACK_SIZE, HIRES_DISPLAY_HEIGHT, HIRES_DISPLAY_WIDTH}, keyboard::Keyboard, quirks::{LoadStore, Quirks}, rng::{RandomSource, Rng}, timing::get_vip_cycles, variant::Variant};
+#[cfg(feature = "std")]
+use crate::savestate::{StateReader, StateWriter};
+
+/**
+ * The CHIP-8 machine itself. R supplies CXNN's random numbers - the built-in seedable generator
+ * unless the host has a better source, such as a hardware RNG. MEMORY_SIZE is how much memory is
+ * stored (see `Memory`)
+ */
+#[derive(Debug, Clone)]
+pub struct Cpu<R = Rng, const MEMORY_SIZE: usize = MAX_MEMORY_SIZE> {
     program_counter: u16,
     v_registers: [u8; 16], // There are 16 general purpose V registers, each capable of storing 8 bits
     i_register: u16, // Address register can hold up to two bytes, but in reality can't be more than 12 bits
     delay_timer: u8,
     sound_timer: u8,
-    memory: Memory,
+    memory: Memory<MEMORY_SIZE>,
     display: Display,
-    stack: Vec<u16>,
+    stack: [u16; STACK_SIZE],
+    stack_pointer: usize, // Number of return addresses on the stack
     keyboard: Keyboard,
     halted: bool,
+    variant: Variant,
+    quirks: Quirks,
+    waiting_for_vblank: bool, // Set after drawing when the display wait quirk is enabled
+    rpl_flags: [u8; 16], // SUPER-CHIP "RPL user flags" which can be saved and restored with FX75 and FX85
+    exited: bool,
+    pitch: u8, // XO-CHIP audio playback rate, set by FX3A
+    rng: R, // Source of CXNN's random numbers, owned by the CPU so runs can be reproduced
+    last_cycles: u32, // COSMAC VIP machine cycles taken by the last instruction executed
 }
 
-impl Cpu {
-    pub fn new(memory: Memory, display: Display, keyboard: Keyboard) -> Self {
+impl<R: RandomSource, const MEMORY_SIZE: usize> Cpu<R, MEMORY_SIZE> {
+    /**
+     * A const fn, so the whole machine can be built into a static on hosts with little stack
+     */
+    pub const fn new(memory: Memory<MEMORY_SIZE>, display: Display, keyboard: Keyboard, rng: R, variant: Variant, quirks: Quirks) -> Self {
         Self {
             program_counter: 0x200,
             v_registers: [0; 16],
@@ -25,189 +43,345 @@ impl Cpu {
             sound_timer: 0,
             memory,
             display,
-            stack: Vec::new(),
+            stack: [0; STACK_SIZE],
+            stack_pointer: 0,
             keyboard,
-            halted: false
+            halted: false,
+            variant,
+            quirks,
+            waiting_for_vblank: false,
+            rpl_flags: [0; 16],
+            exited: false,
+            pitch: 64,
+            rng,
+            last_cycles: 0
         }
     }
 
-    pub fn load_program(&mut self, rom: Rom) {
-        self.memory.load_program(rom);
+    pub fn load_program(&mut self, program: &[u8]) -> Result<(), EmulatorError> {
+        self.memory.load_program(program)
     }
 
-    pub fn get_display_state(&self) -> &[[u8; DISPLAY_HEIGHT]; DISPLAY_WIDTH] {
+    pub fn get_display_state(&self) -> &[[u8; HIRES_DISPLAY_HEIGHT]; HIRES_DISPLAY_WIDTH] {
         &self.display.get_screen_state()
     }
 
+    pub fn get_display(&self) -> &Display {
+        &self.display
+    }
+
+    pub fn get_display_width(&self) -> usize {
+        self.display.get_width()
+    }
+
+    pub fn get_display_height(&self) -> usize {
+        self.display.get_height()
+    }
+
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    pub fn get_pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    pub fn get_variant(&self) -> Variant {
+        self.variant
+    }
+
+    pub fn get_quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn get_program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    pub fn get_v_registers(&self) -> &[u8; 16] {
+        &self.v_registers
+    }
+
+    pub fn get_i_register(&self) -> u16 {
+        self.i_register
+    }
+
+    pub fn get_stack(&self) -> &[u16] {
+        &self.stack[..self.stack_pointer]
+    }
+
+    pub fn get_delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn get_sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn get_memory(&self) -> &Memory<MEMORY_SIZE> {
+        &self.memory
+    }
+
+    pub fn get_memory_mut(&mut self) -> &mut Memory<MEMORY_SIZE> {
+        &mut self.memory
+    }
+
+    /**
+     * The opcode at the program counter, which will be executed next
+     */
+    pub fn get_next_opcode(&self) -> Option<u16> {
+        let hi_byte = self.memory.peek(self.program_counter).ok()? as u16;
+        let lo_byte = self.memory.peek(self.program_counter.checked_add(1)?).ok()? as u16;
+        Some((hi_byte << 8) | lo_byte)
+    }
+
+    pub fn set_program_counter(&mut self, addr: u16) {
+        self.program_counter = addr;
+    }
+
+    pub fn set_v_register(&mut self, register: usize, value: u8) {
+        self.v_registers[register] = value;
+    }
+
+    pub fn set_i_register(&mut self, value: u16) {
+        self.i_register = value;
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn is_waiting_for_vblank(&self) -> bool {
+        self.waiting_for_vblank
+    }
+
+    /**
+     * How long the last instruction would have taken on the COSMAC VIP, in machine cycles
+     */
+    pub fn get_last_cycles(&self) -> u32 {
+        self.last_cycles
+    }
+
     pub fn get_display_pixel(&self, x: usize, y: usize) -> u8 {
         self.display.get_pixel(x, y)
     }
 
-    pub fn press_key(&mut self, key: u8) {
-        self.keyboard.press_key(key);
+    pub fn press_key(&mut self, key: u8) -> Result<(), EmulatorError> {
+        self.keyboard.press_key(key)
     }
 
-    pub fn release_key(&mut self, key: u8) {
-        self.keyboard.release_key(key);
+    pub fn release_key(&mut self, key: u8) -> Result<(), EmulatorError> {
+        self.keyboard.release_key(key)
     }
 
-    pub fn execute(&mut self) {
+    pub fn execute(&mut self) -> Result<(), EmulatorError> {
+        // The interpreter has gone once 00FD has run, so there's nothing left to execute
+        if self.exited {
+            self.last_cycles = 0;
+            return Ok(());
+        }
+
+        // Nothing more can happen this frame once a sprite has been drawn with the display wait quirk
+        if self.waiting_for_vblank {
+            self.last_cycles = 0;
+            return Ok(());
+        }
+
         if self.halted {
             self.program_counter -= 2;
         }
 
-        let pc = self.program_counter.clone();
-        let instr = self.get_instruction();
-        let prefix = (instr >> 12) & 0xF;
-
-        // println!("V - {:?}, I - 0x{:04X}", self.v_registers, self.i_register);
-        // println!("Executing PC 0x{:04X} - 0x{:04X}", pc, instr);
-        // for row in self.display.get_screen_state() {
-        //     println!("{:?}", row);
-        // }
-
-        match prefix {
-            0x0 => self.do_execute_zero_instr(instr),
-            0x1 => self.do_jump_to_address(instr),
-            0x2 => self.do_execute_subroutine(instr),
-            0x3 => self.do_skip_instruction(instr, (instr & 0xFF) as u8, true),
-            0x4 => self.do_skip_instruction(instr, (instr & 0xFF) as u8, false),
-            0x5 => self.do_skip_instruction(instr, self.v_registers[((instr >> 4) & 0xF) as usize], true),
-            0x6 => self.do_store_number_in_v(instr),
-            0x7 => self.do_add_value_to_vx(instr),
-            0x8 => self.do_execute_8_instr(instr),
-            0x9 => self.do_skip_instruction(instr, self.v_registers[((instr >> 4) & 0xF) as usize], false),
-            0xA => self.do_store_memory_address_in_i(instr),
-            0xB => self.program_counter = (instr & 0xFFF) + (self.v_registers[0x0] as u16),
-            0xC => self.do_set_vx_to_random_with_mask(instr),
-            0xD => self.do_draw_sprite(instr),
-            0xE => self.do_execute_e_instr(instr),
-            0xF => self.do_execute_f_instr(instr),
-            _   => panic!("Operation not found - 0x{:04X}", instr)
+        let pc = self.program_counter;
+        let opcode = self.get_instruction()?;
+        let operand = match Instruction::has_operand(opcode) {
+            true  => self.get_instruction()?,
+            false => 0
+        };
+
+        // Instructions from later variants are treated as unknown, just as the original interpreter would
+        let instr = match Instruction::decode(opcode, operand) {
+            Some(instr) if instr.get_variant() <= self.variant => instr,
+            _ => return Err(EmulatorError::UnknownOpcode { pc, opcode })
+        };
+
+        // Some instructions take longer depending on the registers they were given
+        let v_registers = self.v_registers;
+
+        match instr {
+            Instruction::ClearScreen                      => self.display.clear_screen(),
+            Instruction::Return                           => self.do_return_from_subroutine()?,
+            Instruction::ScrollDown { rows }              => self.display.scroll_down(rows as usize),
+            Instruction::ScrollUp { rows }                => self.display.scroll_up(rows as usize),
+            Instruction::ScrollRight                      => self.display.scroll_right(4),
+            Instruction::ScrollLeft                       => self.display.scroll_left(4),
+            Instruction::Exit                             => self.exited = true,
+            Instruction::LowRes                           => self.display.set_hires(false),
+            Instruction::HighRes                          => self.display.set_hires(true),
+            Instruction::Jump { addr }                    => self.do_jump_to_address(addr),
+            Instruction::Call { addr }                    => self.do_execute_subroutine(addr)?,
+            Instruction::SkipIfEqual { x, value }         => self.do_skip_instruction(x as usize, value, true)?,
+            Instruction::SkipIfNotEqual { x, value }      => self.do_skip_instruction(x as usize, value, false)?,
+            Instruction::SkipIfRegistersEqual { x, y }    => self.do_skip_instruction(x as usize, self.v_registers[y as usize], true)?,
+            Instruction::StoreRange { x, y }              => self.do_store_v_register_range(x as usize, y as usize)?,
+            Instruction::LoadRange { x, y }               => self.do_fill_v_register_range(x as usize, y as usize)?,
+            Instruction::SetRegister { x, value }         => self.do_store_number_in_v(x as usize, value),
+            Instruction::AddValue { x, value }            => self.do_add_value_to_vx(x as usize, value),
+            Instruction::Copy { x, y }                    => self.v_registers[x as usize] = self.v_registers[y as usize],
+            Instruction::Or { x, y }                      => self.do_bitwise_op(x as usize, y as usize, |vx, vy| vx | vy),
+            Instruction::And { x, y }                     => self.do_bitwise_op(x as usize, y as usize, |vx, vy| vx & vy),
+            Instruction::Xor { x, y }                     => self.do_bitwise_op(x as usize, y as usize, |vx, vy| vx ^ vy),
+            Instruction::Add { x, y }                     => self.do_add_vy_to_vx(x as usize, y as usize),
+            Instruction::Subtract { x, y }                => self.do_subtract_vy_from_vx(x as usize, y as usize),
+            Instruction::ShiftRight { x, y }              => self.do_shift_bit_right(x as usize, y as usize),
+            Instruction::SubtractReverse { x, y }         => self.do_subtract_vx_from_vy(x as usize, y as usize),
+            Instruction::ShiftLeft { x, y }               => self.do_shift_bit_left(x as usize, y as usize),
+            Instruction::SkipIfRegistersNotEqual { x, y } => self.do_skip_instruction(x as usize, self.v_registers[y as usize], false)?,
+            Instruction::SetI { addr }                    => self.i_register = addr,
+            Instruction::JumpWithOffset { addr }          => self.do_jump_with_offset(addr),
+            Instruction::Random { x, mask }               => self.do_set_vx_to_random_with_mask(x as usize, mask),
+            Instruction::Draw { x, y, rows }              => self.do_draw_sprite(x as usize, y as usize, rows)?,
+            Instruction::SkipIfKeyPressed { x }           => self.do_key_pressed_skip(x as usize)?,
+            Instruction::SkipIfKeyNotPressed { x }        => self.do_key_not_pressed_skip(x as usize)?,
+            Instruction::SetILong { addr }                => self.i_register = addr,
+            Instruction::SelectPlanes { planes }          => self.display.select_planes(planes),
+            Instruction::GetDelayTimer { x }              => self.v_registers[x as usize] = self.delay_timer,
+            Instruction::AwaitKey { x }                   => self.do_await_key_press(x as usize),
+            Instruction::SetDelayTimer { x }              => self.delay_timer = self.v_registers[x as usize],
+            Instruction::SetSoundTimer { x }              => self.sound_timer = self.v_registers[x as usize],
+            Instruction::AddToI { x }                     => self.do_add_to_i_register(x as usize),
+            Instruction::SetIToFont { x }                 => self.do_set_i_to_sprite_location(x as usize),
+            Instruction::SetIToBigFont { x }              => self.do_set_i_to_big_sprite_location(x as usize),
+            Instruction::StoreBcd { x }                   => self.do_store_binary_coded_decimal(x as usize)?,
+            Instruction::SetPitch { x }                   => self.pitch = self.v_registers[x as usize],
+            Instruction::StoreRegisters { x }             => self.do_store_v_registers(x as usize)?,
+            Instruction::LoadRegisters { x }              => self.do_fill_v_registers(x as usize)?,
+            Instruction::StoreFlags { x }                 => self.do_store_rpl_flags(x as usize),
+            Instruction::LoadFlags { x }                  => self.do_fill_rpl_flags(x as usize),
         };
+
+        let skipped = self.program_counter != pc.wrapping_add(instr.get_size());
+        self.last_cycles = get_vip_cycles(instr, &v_registers, skipped);
+
+        Ok(())
+    }
+
+    /**
+     * Signal the start of a new frame, releasing a CPU that is waiting to draw
+     */
+    pub fn vblank(&mut self) {
+        self.waiting_for_vblank = false;
     }
 
-    pub fn decrement_timer(&mut self) {
+    /**
+     * Count both timers down towards 0 - called at 60 Hz
+     */
+    pub fn decrement_timers(&mut self) {
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
     }
 
-    fn get_instruction(&mut self) -> u16 {
-        let hi_byte = self.memory.read(self.program_counter) as u16;
-        let lo_byte = self.memory.read(self.program_counter + 1) as u16;
+    fn get_instruction(&mut self) -> Result<u16, EmulatorError> {
+        let hi_byte = self.memory.peek(self.program_counter)? as u16;
+        let lo_byte = self.memory.peek(Self::offset_addr(self.program_counter, 1)?)? as u16;
 
         // Increment Program Counter - 2 spots as each instruction is 2 bytes in memory
-        self.program_counter += 2;
+        self.program_counter = Self::offset_addr(self.program_counter, 2)?;
 
         // Chip 8 is Big endian
-        (hi_byte << 8) | lo_byte
+        Ok((hi_byte << 8) | lo_byte)
     }
 
-    fn do_execute_zero_instr(&mut self, instr: u16) {
-        let lower = instr & 0xFF;
-
-        match lower {
-            0xE0 => self.display.clear_screen(),
-            0xEE => self.do_return_from_subroutine(),
-            _    => panic!("Operation not found - 0x{:04X}", instr)
+    fn get_skip_size(&self) -> Result<u16, EmulatorError> {
+        // XO-CHIP's F000 NNNN is four bytes long, so skipping over it has to skip both words
+        let next_instr = ((self.memory.peek(self.program_counter)? as u16) << 8) | self.memory.peek(Self::offset_addr(self.program_counter, 1)?)? as u16;
+        // Only the size is needed, so the operand word doesn't have to be read
+        match Instruction::decode(next_instr, 0) {
+            Some(instr) if instr.get_variant() <= self.variant => Ok(instr.get_size()),
+            _ => Ok(2)
         }
     }
 
-    fn do_execute_8_instr(&mut self, instr: u16) {
-        let x = (instr >> 8) & 0xF;
-        let y = (instr >> 4) & 0xF;
-
-        match instr & 0xF {
-            0x0 => self.v_registers[x as usize] = self.v_registers[y as usize],
-            0x1 => self.v_registers[x as usize] |= self.v_registers[y as usize],
-            0x2 => self.v_registers[x as usize] &= self.v_registers[y as usize],
-            0x3 => self.v_registers[x as usize] ^= self.v_registers[y as usize],
-            0x4 => self.do_add_vy_to_vx(instr),
-            0x5 => self.do_subtract_vy_from_vx(instr),
-            0x6 => self.do_shift_bit_right(instr),
-            0x7 => self.do_subtract_vx_from_vy(instr),
-            0xE => self.do_shift_bit_left(instr),
-            _   => panic!("Operation not found - 0x{:04X}", instr)
-        }
+    fn do_jump_to_address(&mut self, addr: u16) {
+        self.program_counter = addr;
     }
 
-    fn do_execute_e_instr(&mut self, instr: u16) {
-        let lower = instr & 0xFF;
-
-        match lower {
-            0x9E => self.do_key_pressed_skip(instr),
-            0xA1 => self.do_key_not_pressed_skip(instr),
-            _    => panic!("Operation not found - 0x{:04X}", instr)
-        }
+    fn do_jump_with_offset(&mut self, addr: u16) {
+        // BNNN jumps to NNN + V0, or with the jump quirk BXNN jumps to XNN + VX
+        let register = match self.quirks.jump {
+            true  => ((addr >> 8) & 0xF) as usize,
+            false => 0x0
+        };
+        self.program_counter = addr + (self.v_registers[register] as u16);
     }
 
-    fn do_execute_f_instr(&mut self, instr: u16) {
-        let lower = instr & 0xFF;
-
-        match lower {
-            0x07 => self.do_store_delay_timer(instr),
-            0x0A => self.do_await_key_press(instr),
-            0x15 => self.do_set_delay_timer(instr),
-            0x18 => self.do_set_sound_timer(instr),
-            0x1E => self.do_add_to_i_register(instr),
-            0x29 => self.do_set_i_to_sprite_location(instr),
-            0x33 => self.do_store_binary_coded_decimal(instr),
-            0x55 => self.do_store_v_registers(instr),
-            0x65 => self.do_fill_v_registers(instr),
-            _    => panic!("Operation not found - 0x{:04X}", instr)
+    fn do_return_from_subroutine(&mut self) -> Result<(), EmulatorError> {
+        if self.stack_pointer == 0 {
+            return Err(EmulatorError::StackUnderflow { pc: self.program_counter - 2 });
         }
-    }
 
-    fn do_jump_to_address(&mut self, instr: u16) {
-        self.program_counter = instr & 0xFFF;
+        self.stack_pointer -= 1;
+        self.program_counter = self.stack[self.stack_pointer];
+
+        Ok(())
     }
 
-    fn do_return_from_subroutine(&mut self) {
-        // self.program_counter = self.stack.pop();
-        self.program_counter = match self.stack.pop() {
-            Some(val) => val,
-            None => panic!("Stack was empty!")
+    fn do_execute_subroutine(&mut self, addr: u16) -> Result<(), EmulatorError> {
+        if self.stack_pointer >= STACK_SIZE {
+            return Err(EmulatorError::StackOverflow { pc: self.program_counter - 2 });
         }
-    }
 
-    fn do_execute_subroutine(&mut self, instr: u16) {
-        let addr = instr & 0xFFF;
-        self.stack.push(self.program_counter);
+        self.stack[self.stack_pointer] = self.program_counter;
+        self.stack_pointer += 1;
         self.program_counter = addr;
-    }
 
-    fn do_skip_instruction(&mut self, instr: u16, value: u8, should_equal_val: bool) {
-        let register_x = (instr >> 8) & 0xF;
+        Ok(())
+    }
 
+    fn do_skip_instruction(&mut self, register_x: usize, value: u8, should_equal_val: bool) -> Result<(), EmulatorError> {
         let condition = match should_equal_val {
-            true  => self.v_registers[register_x as usize] == value,
-            false => self.v_registers[register_x as usize] != value
+            true  => self.v_registers[register_x] == value,
+            false => self.v_registers[register_x] != value
         };
 
         // Skip next instruction IFF value in register_x = value
         self.program_counter = match condition {
-            true  => self.program_counter + 2,
+            true  => Self::offset_addr(self.program_counter, self.get_skip_size()?)?,
             false => self.program_counter
-        }
+        };
+
+        Ok(())
     }
 
-    fn do_store_number_in_v(&mut self, instr: u16) {
-        let v = ((instr >> 8) & 0xF) as usize;
-        let value = (instr & 0xFF) as u8;
-        self.v_registers[v] = value;
+    fn do_store_number_in_v(&mut self, register_x: usize, value: u8) {
+        self.v_registers[register_x] = value;
     }
 
-    fn do_add_value_to_vx(&mut self, instr: u16) {
-        let register_x = ((instr >> 8) & 0xF) as usize;
-        let value = (instr & 0xFF) as u8;
+    fn do_add_value_to_vx(&mut self, register_x: usize, value: u8) {
         self.v_registers[register_x] = self.v_registers[register_x].wrapping_add(value);
     }
 
-    fn do_add_vy_to_vx(&mut self, instr: u16) {
-        let register_x = ((instr >> 8) & 0xF) as usize;
-        let register_y = ((instr >> 4) & 0xF) as usize;
+    fn do_bitwise_op(&mut self, register_x: usize, register_y: usize, op: fn(u8, u8) -> u8) {
+        self.v_registers[register_x] = op(self.v_registers[register_x], self.v_registers[register_y]);
+
+        // The COSMAC VIP used VF as scratch space for the logic operations, leaving it 0
+        if self.quirks.vf_reset {
+            self.v_registers[0xF] = 0;
+        }
+    }
 
+    fn do_add_vy_to_vx(&mut self, register_x: usize, register_y: usize) {
         // Set if carry
         let carry = match self.v_registers[register_x].checked_add(self.v_registers[register_y]) {
             Some(_) => 0,
@@ -218,10 +392,7 @@ impl Cpu {
         self.v_registers[0xF] = carry;
     }
 
-    fn do_subtract_vy_from_vx(&mut self, instr: u16) {
-        let register_x = ((instr >> 8) & 0xF) as usize;
-        let register_y = ((instr >> 4) & 0xF) as usize;
-
+    fn do_subtract_vy_from_vx(&mut self, register_x: usize, register_y: usize) {
         // Set if not borrow
         let borrow = match self.v_registers[register_x].checked_sub(self.v_registers[register_y]) {
             Some(_) => 1,
@@ -232,10 +403,7 @@ impl Cpu {
         self.v_registers[0xF] = borrow;
     }
 
-    fn do_subtract_vx_from_vy(&mut self, instr: u16) {
-        let register_x = ((instr >> 8) & 0xF) as usize;
-        let register_y = ((instr >> 4) & 0xF) as usize;
-
+    fn do_subtract_vx_from_vy(&mut self, register_x: usize, register_y: usize) {
         // Set if not borrow
         let borrow = match self.v_registers[register_y].checked_sub(self.v_registers[register_x]) {
             Some(_) => 1,
@@ -246,162 +414,317 @@ impl Cpu {
         self.v_registers[0xF] = borrow;
     }
 
-    fn do_shift_bit_right(&mut self, instr: u16) {
+    fn do_shift_bit_right(&mut self, register_x: usize, register_y: usize) {
         // Shift VY right one bit and store in VX. VF is set to least significant bit before the change
-        // VY is unchanged
-        let register_x = ((instr >> 8) & 0xF) as usize;
-        let register_y = ((instr >> 4) & 0xF) as usize;
-        let val = self.v_registers[register_y];
+        // VY is unchanged. With the shift quirk VX is shifted in place instead
+        let val = self.get_shift_source(register_x, register_y);
 
         self.v_registers[register_x] = val >> 1;
         self.v_registers[0xF] = val & 0x1;
     }
 
-    fn do_shift_bit_left(&mut self, instr: u16) {
+    fn do_shift_bit_left(&mut self, register_x: usize, register_y: usize) {
         // Shift VY left one bit and store in VX. VF is set to most significant bit before the change
-        // VY is unchanged
-        let register_x = ((instr >> 8) & 0xF) as usize;
-        let register_y = ((instr >> 4) & 0xF) as usize;
-        let val = self.v_registers[register_y];
+        // VY is unchanged. With the shift quirk VX is shifted in place instead
+        let val = self.get_shift_source(register_x, register_y);
 
         self.v_registers[register_x] = val << 1;
         self.v_registers[0xF] = val >> 7;
     }
 
-    fn do_store_memory_address_in_i(&mut self, instr: u16) {
-        let addr = instr & 0xFFF;
-        self.i_register = addr;
+    fn get_shift_source(&self, register_x: usize, register_y: usize) -> u8 {
+        match self.quirks.shift {
+            true  => self.v_registers[register_x],
+            false => self.v_registers[register_y]
+        }
     }
 
-    fn do_set_vx_to_random_with_mask(&mut self, instr: u16) {
-        let register_x = (instr >> 8) & 0xF;
-        let value = (instr & 0xFF) as u8;
-        let random_val = random::<u8>();
-        self.v_registers[register_x as usize] = random_val & value;
+    fn do_set_vx_to_random_with_mask(&mut self, register_x: usize, mask: u8) {
+        let random_val = self.rng.next_u8();
+        self.v_registers[register_x] = random_val & mask;
     }
 
-    fn do_draw_sprite(&mut self, instr: u16) {
+    fn do_draw_sprite(&mut self, register_x: usize, register_y: usize, rows: u8) -> Result<(), EmulatorError> {
         // DXYN - Draw sprite at position VX, VY with N bytes of sprite data
         // Sprite data starts at the address stored in register I
-        let register_x = (instr >> 8) & 0xF;
-        let register_y = (instr >> 4) & 0xF;
-        let end_addr = self.i_register + (instr & 0xF);
+        // On SUPER-CHIP, DXY0 draws a 16x16 sprite made up of 32 bytes (two bytes per row)
+        let (sprite_width, sprite_height) = match rows {
+            0 if self.variant >= Variant::SuperChip => (16, 16),
+            n => (8, n as u16)
+        };
+        let bytes_per_row = sprite_width / 8;
 
-        let x_pos: u8 = self.v_registers[register_x as usize];
-        let mut y_pos: u8 = self.v_registers[register_y as usize];
+        // The starting position always wraps around the screen, but the rest of the sprite is
+        // clipped at the edges when the clipping quirk is enabled
+        let width = self.display.get_width();
+        let height = self.display.get_height();
+        let x_pos = self.v_registers[register_x] as usize % width;
+        let y_pos = self.v_registers[register_y] as usize % height;
+
+        // XO-CHIP draws the sprite once for each selected plane, with the data for each plane
+        // following directly on from the previous one
+        let selected_planes = self.display.get_selected_planes();
+        let mut plane_offset = 0;
 
         // let mut pixels_unset = false;
         let mut any_flipped = false;
-        for i in self.i_register..end_addr {
-            // Sprite data will be one byte. Draw 8 bits starting from the x_pos
-            // Each "sprite data" we read will be at the next y_pos
-            let sprite_data = self.memory.read(i);
-            for x in (0..8).rev() {
-                let val = get_bit_val(sprite_data, x);
-                // let x_idx = ((x_pos + 7 - x) as usize) % DISPLAY_WIDTH;
-                let x_idx = ((x_pos as usize) + 7 - (x as usize)) % DISPLAY_WIDTH;
-                let y_idx = (y_pos as usize) % DISPLAY_HEIGHT;
-                any_flipped = self.display.set_pixel(x_idx, y_idx, val) || any_flipped;
+        for plane in [0x1, 0x2] {
+            if selected_planes & plane == 0 {
+                continue;
+            }
+
+            for row in 0..sprite_height {
+                // Sprite data will be one byte per 8 pixels. Draw each bit starting from the x_pos
+                // Each row of sprite data we read will be at the next y_pos
+                let y_idx = y_pos + row as usize;
+                if self.quirks.clipping && y_idx >= height {
+                    break;
+                }
+
+                for col in 0..bytes_per_row {
+                    let sprite_data = self.memory.read(Self::offset_addr(self.i_register, plane_offset + row * bytes_per_row + col)?)?;
+                    for x in (0..8).rev() {
+                        let val = get_bit_val(sprite_data, x);
+                        let x_idx = x_pos + (col as usize) * 8 + 7 - (x as usize);
+                        if self.quirks.clipping && x_idx >= width {
+                            continue;
+                        }
+
+                        let (x_idx, y_idx) = (x_idx % width, y_idx % height);
+                        any_flipped = self.display.set_pixel(x_idx, y_idx, plane, val) || any_flipped;
+                    }
+                }
             }
 
-            y_pos = y_pos.wrapping_add(1);
+            plane_offset += sprite_height * bytes_per_row;
         }
 
         // Set VF to 1 if any pixel was flipped in the display, 0 otherwise
         self.v_registers[0xF] = any_flipped as u8;
+
+        if self.quirks.display_wait {
+            self.waiting_for_vblank = true;
+        };
+
+        Ok(())
     }
 
-    fn do_key_pressed_skip(&mut self, instr: u16) {
+    fn do_key_pressed_skip(&mut self, register_x: usize) -> Result<(), EmulatorError> {
         // Skip next instruction if key corresponding to hex value in X is pressed
-        let register_x = (instr >> 8) & 0xF;
-        self.program_counter = match self.keyboard.is_pressed(self.v_registers[register_x as usize]) {
-            true  => self.program_counter + 2,
+        self.program_counter = match self.keyboard.is_pressed(self.v_registers[register_x])? {
+            true  => Self::offset_addr(self.program_counter, self.get_skip_size()?)?,
             false => self.program_counter
-        }
+        };
+
+        Ok(())
     }
 
-    fn do_key_not_pressed_skip(&mut self, instr: u16) {
+    fn do_key_not_pressed_skip(&mut self, register_x: usize) -> Result<(), EmulatorError> {
         // Skip next instruction if key corresponding to hex value in X is not pressed
-        let register_x = (instr >> 8) & 0xF;
-        self.program_counter = match self.keyboard.is_pressed(self.v_registers[register_x as usize]) {
+        self.program_counter = match self.keyboard.is_pressed(self.v_registers[register_x])? {
             true  => self.program_counter,
-            false => self.program_counter + 2
-        }
-    }
+            false => Self::offset_addr(self.program_counter, self.get_skip_size()?)?
+        };
 
-    fn do_store_delay_timer(&mut self, instr: u16) {
-        let register_x = (instr >> 8) & 0xF;
-        self.v_registers[register_x as usize] = self.delay_timer;
+        Ok(())
     }
 
-    fn do_await_key_press(&mut self, instr: u16) {
+    fn do_await_key_press(&mut self, register_x: usize) {
         // Await a key press and store the result in register X
-        let register_x = (instr >> 8) & 0xF;
         self.halted = true;
         match self.keyboard.get_pressed() {
             Some(key) => {
                 self.halted = false;
-                self.v_registers[register_x as usize] = key;
+                self.v_registers[register_x] = key;
             },
             None => ()
         }
     }
 
-    fn do_set_delay_timer(&mut self, instr: u16) {
-        let register_x = (instr >> 8) & 0xF;
-        self.delay_timer = self.v_registers[register_x as usize];
-    }
-
-    fn do_set_sound_timer(&mut self, instr: u16) {
-        let register_x = (instr >> 8) & 0xF;
-        self.sound_timer = self.v_registers[register_x as usize];
-    }
-
-    fn do_add_to_i_register(&mut self, instr: u16) {
+    fn do_add_to_i_register(&mut self, register_x: usize) {
         // Add the value of VX to I
-        let register_x = (instr >> 8) & 0xF;
-        self.i_register = self.i_register.wrapping_add(self.v_registers[register_x as usize] as u16);
+        self.i_register = self.i_register.wrapping_add(self.v_registers[register_x] as u16);
     }
 
-    fn do_set_i_to_sprite_location(&mut self, instr: u16) {
+    fn do_set_i_to_sprite_location(&mut self, register_x: usize) {
         // Sets I to location of the sprite for character in VX - characters 0-F represented by 4x5 font
-        let register_x = (instr >> 8) & 0xF;
-        let value = self.v_registers[register_x as usize];
+        let value = self.v_registers[register_x];
         self.i_register = (value as u16) * 5;
     }
 
-    fn do_store_binary_coded_decimal(&mut self, instr: u16) {
+    fn do_set_i_to_big_sprite_location(&mut self, register_x: usize) {
+        // Sets I to location of the 8x10 sprite for the digit in VX
+        let value = self.v_registers[register_x] & 0xF;
+        self.i_register = BIG_FONT_START + (value as u16) * 10;
+    }
+
+    fn do_store_binary_coded_decimal(&mut self, register_x: usize) -> Result<(), EmulatorError> {
         // Store binary coded decimal equivalent of value in register X at I, I + 1, and I + 2
-        let register_x = (instr >> 8) & 0xF;
-        let value = self.v_registers[register_x as usize];
+        let value = self.v_registers[register_x];
 
         // BCD can be up to 3 digits (max value of 255).
         let most_significant_digit = value / 100;
         let middle_digit = (value / 10) % 10;
         let least_significant_digit = value % 10;
 
-        self.memory.write(self.i_register, most_significant_digit);
-        self.memory.write(self.i_register + 1, middle_digit);
-        self.memory.write(self.i_register + 2, least_significant_digit);
+        self.memory.write(self.i_register, most_significant_digit)?;
+        self.memory.write(Self::offset_addr(self.i_register, 1)?, middle_digit)?;
+        self.memory.write(Self::offset_addr(self.i_register, 2)?, least_significant_digit)?;
+
+        Ok(())
+    }
+
+    fn do_store_v_registers(&mut self, register_x: usize) -> Result<(), EmulatorError> {
+        for i in 0..=register_x {
+            self.memory.write(Self::offset_addr(self.i_register, i as u16)?, self.v_registers[i])?;
+        }
+
+        // Set I Register to I + X + 1, or wherever the load/store quirk leaves it
+        self.increment_i_after_load_store(register_x);
+
+        Ok(())
     }
 
-    fn do_store_v_registers(&mut self, instr: u16) {
-        let register_x = (instr >> 8) & 0xF;
+    fn do_fill_v_registers(&mut self, register_x: usize) -> Result<(), EmulatorError> {
         for i in 0..=register_x {
-            self.memory.write(self.i_register + i, self.v_registers[i as usize]);
+            self.v_registers[i] = self.memory.read(Self::offset_addr(self.i_register, i as u16)?)?;
+        }
+
+        // Set I Register to I + X + 1, or wherever the load/store quirk leaves it
+        self.increment_i_after_load_store(register_x);
+
+        Ok(())
+    }
+
+    fn increment_i_after_load_store(&mut self, register_x: usize) {
+        match self.quirks.load_store {
+            LoadStore::Increment    => self.i_register = self.i_register.wrapping_add(register_x as u16 + 1),
+            LoadStore::IncrementByX => self.i_register = self.i_register.wrapping_add(register_x as u16),
+            LoadStore::Unchanged    => ()
         }
+    }
 
-        // Set I Register to I + X + 1
-        self.i_register = self.i_register + register_x + 1;
+    fn do_store_rpl_flags(&mut self, register_x: usize) {
+        // Store V0 to VX (inclusive) in the RPL user flags
+        for i in 0..=register_x {
+            self.rpl_flags[i] = self.v_registers[i];
+        }
     }
 
-    fn do_fill_v_registers(&mut self, instr: u16) {
-        let register_x = (instr >> 8) & 0xF;
+    fn do_fill_rpl_flags(&mut self, register_x: usize) {
+        // Fill V0 to VX (inclusive) from the RPL user flags
         for i in 0..=register_x {
-            self.v_registers[i as usize] = self.memory.read(self.i_register + i);
+            self.v_registers[i] = self.rpl_flags[i];
         }
+    }
 
-        // Set I Register to I + X + 1
-        self.i_register = self.i_register + register_x + 1;
+    fn do_store_v_register_range(&mut self, register_x: usize, register_y: usize) -> Result<(), EmulatorError> {
+        // 5XY2 - Store VX to VY (inclusive, in either order) in memory starting at I. I is unchanged
+        for offset in 0..=register_x.abs_diff(register_y) {
+            let register = Self::get_range_register(register_x, register_y, offset);
+            self.memory.write(Self::offset_addr(self.i_register, offset as u16)?, self.v_registers[register])?;
+        }
+
+        Ok(())
+    }
+
+    fn do_fill_v_register_range(&mut self, register_x: usize, register_y: usize) -> Result<(), EmulatorError> {
+        // 5XY3 - Fill VX to VY (inclusive, in either order) from memory starting at I. I is unchanged
+        for offset in 0..=register_x.abs_diff(register_y) {
+            let register = Self::get_range_register(register_x, register_y, offset);
+            self.v_registers[register] = self.memory.read(Self::offset_addr(self.i_register, offset as u16)?)?;
+        }
+
+        Ok(())
+    }
+
+    fn get_range_register(register_x: usize, register_y: usize, offset: usize) -> usize {
+        match register_x <= register_y {
+            true  => register_x + offset,
+            false => register_x - offset
+        }
+    }
+
+    /**
+     * The address offset bytes on from addr. Going past 0xFFFF is out of bounds, rather than
+     * wrapping around to the start of memory
+     */
+    fn offset_addr(addr: u16, offset: u16) -> Result<u16, EmulatorError> {
+        addr.checked_add(offset).ok_or(EmulatorError::MemoryOutOfBounds { addr })
+    }
+}
+
+/**
+ * Save states capture the random number generator, so are only available with the built-in one
+ */
+#[cfg(feature = "std")]
+impl Cpu<Rng> {
+    /**
+     * Write the whole machine to a save state - see savestate.rs for the layout
+     */
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.variant.get_id());
+        writer.write_u8(self.quirks.to_bits());
+        writer.write_u16(self.program_counter);
+        writer.write_bytes(&self.v_registers);
+        writer.write_u16(self.i_register);
+        writer.write_u8(self.delay_timer);
+        writer.write_u8(self.sound_timer);
+
+        // The stack is always written in full so that every state is the same size
+        writer.write_u8(self.stack_pointer as u8);
+        for i in 0..STACK_SIZE {
+            writer.write_u16(self.get_stack().get(i).copied().unwrap_or(0));
+        }
+
+        writer.write_u8((self.halted as u8) | ((self.waiting_for_vblank as u8) << 1) | ((self.exited as u8) << 2));
+        writer.write_bytes(&self.rpl_flags);
+        writer.write_u8(self.pitch);
+        writer.write_u64(self.rng.get_state());
+
+        self.memory.save_state(writer);
+        self.display.save_state(writer);
+        self.keyboard.save_state(writer);
+    }
+
+    /**
+     * Restore the machine from a save state. On error the CPU may be partially restored, so callers
+     * should load into a copy and only keep it if this succeeds
+     */
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), EmulatorError> {
+        if Variant::from_id(reader.read_u8()?) != Some(self.variant) {
+            return Err(EmulatorError::InvalidSaveState { reason: "state was saved with a different variant" });
+        }
+        self.quirks = Quirks::from_bits(reader.read_u8()?);
+        self.program_counter = reader.read_u16()?;
+        self.v_registers.copy_from_slice(reader.read_bytes(16)?);
+        self.i_register = reader.read_u16()?;
+        self.delay_timer = reader.read_u8()?;
+        self.sound_timer = reader.read_u8()?;
+
+        let depth = reader.read_u8()? as usize;
+        if depth > STACK_SIZE {
+            return Err(EmulatorError::InvalidSaveState { reason: "stack is too deep" });
+        }
+        for i in 0..STACK_SIZE {
+            let addr = reader.read_u16()?;
+            self.stack[i] = match i < depth {
+                true  => addr,
+                false => 0
+            };
+        }
+        self.stack_pointer = depth;
+
+        let flags = reader.read_u8()?;
+        self.halted = flags & 0x1 != 0;
+        self.waiting_for_vblank = flags & 0x2 != 0;
+        self.exited = flags & 0x4 != 0;
+        self.rpl_flags.copy_from_slice(reader.read_bytes(16)?);
+        self.pitch = reader.read_u8()?;
+        self.rng.set_state(reader.read_u64()?);
+
+        self.memory.load_state(reader)?;
+        self.display.load_state(reader)?;
+        self.keyboard.load_state(reader)
     }
 }
\ No newline at end of file
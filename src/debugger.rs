@@ -0,0 +1,300 @@
+use std::io::{self, BufRead, Write};
+
+use crate::{cpu::Cpu, disassembler::Line, instruction::Instruction, memory::WatchKind};
+
+const HELP: &str = "\
+Commands:
+  s, step [n]        Execute n instructions (default 1)
+  n, next            Execute one instruction, stepping over subroutine calls
+  c, continue        Run until a breakpoint or watchpoint is hit
+  u, until <addr>    Run until PC reaches addr
+  b, break [addr]    Set a breakpoint at addr (default PC), or list breakpoints
+  d, delete <addr>   Remove the breakpoint at addr
+  w, watch <addr>    Pause when the program writes to addr
+  rw, rwatch <addr>  Pause when the program reads from addr
+  dw <addr>          Remove any watchpoints on addr
+  r, regs            Show registers, timers and the stack
+  x <addr> [len]     Show len bytes of memory starting at addr (default 16)
+  screen             Show the display
+  h, help            Show this message
+  q, quit            Stop emulation
+An empty line repeats the previous command. Addresses are in hex.";
+
+#[derive(Debug)]
+pub struct Debugger {
+    breakpoints: Vec<u16>,
+    steps: Option<usize>,            // Instructions left to execute before pausing, None when running freely
+    run_to: Option<u16>,             // Address to pause at for the until command
+    step_over: Option<(u16, usize)>, // Return address and stack depth to pause at when stepping over a call
+    resuming: bool,                  // Set when continuing, so we don't immediately stop at the same breakpoint again
+    last_command: String,
+}
+
+impl Debugger {
+
+    pub fn new() -> Self {
+        Self {
+            breakpoints: Vec::new(),
+            steps: Some(0),
+            run_to: None,
+            step_over: None,
+            resuming: false,
+            last_command: String::new()
+        }
+    }
+
+    /**
+     * Pause before the next instruction is executed
+     */
+    pub fn pause(&mut self) {
+        self.steps = Some(0);
+    }
+
+    /**
+     * Check whether execution should pause before the CPU's next instruction
+     */
+    pub fn should_break(&mut self, cpu: &Cpu) -> bool {
+        // Nothing is executed while waiting for vblank, so don't count it as a step
+        if cpu.is_waiting_for_vblank() {
+            return false;
+        }
+
+        let pc = cpu.get_program_counter();
+        let resuming = self.resuming;
+        self.resuming = false;
+
+        if let Some(hit) = cpu.get_memory().take_watch_hit() {
+            let access = match hit.kind {
+                WatchKind::Read  => "Read from",
+                WatchKind::Write => "Write to"
+            };
+            println!("{} watched address 0x{:04X}", access, hit.addr);
+            return true;
+        }
+
+        if !resuming && self.breakpoints.contains(&pc) {
+            println!("Breakpoint at 0x{:04X}", pc);
+            return true;
+        }
+
+        if self.run_to == Some(pc) {
+            self.run_to = None;
+            return true;
+        }
+
+        if let Some((return_addr, depth)) = self.step_over {
+            if pc == return_addr && cpu.get_stack().len() == depth {
+                self.step_over = None;
+                return true;
+            }
+        }
+
+        match self.steps {
+            Some(0) => true,
+            Some(n) => {
+                self.steps = Some(n - 1);
+                false
+            },
+            None => false
+        }
+    }
+
+    /**
+     * Read and run debugger commands until one of them resumes execution. Returns false if the
+     * user asked to quit
+     */
+    pub fn prompt(&mut self, cpu: &mut Cpu) -> bool {
+        println!("{}", self.get_current_line(cpu));
+
+        let stdin = io::stdin();
+        loop {
+            print!("(debug) ");
+            io::stdout().flush().unwrap();
+
+            let mut input = String::new();
+            if stdin.lock().read_line(&mut input).unwrap_or(0) == 0 {
+                // End of input - there's no way to get any more commands
+                return false;
+            }
+
+            let input = match input.trim() {
+                "" => self.last_command.clone(),
+                line => line.to_string()
+            };
+            self.last_command = input.clone();
+
+            let mut args = input.split_whitespace();
+            let command = args.next().unwrap_or("");
+            let arg = args.next();
+
+            match command {
+                "s" | "step" => {
+                    let count = arg.and_then(|n| n.parse::<usize>().ok()).unwrap_or(1);
+                    return self.resume(Some(count.max(1)));
+                },
+                "n" | "next" => {
+                    let pc = cpu.get_program_counter();
+                    if let Some(Instruction::Call { .. }) = self.get_instruction(cpu, pc) {
+                        self.step_over = Some((pc + 2, cpu.get_stack().len()));
+                        return self.resume(None);
+                    }
+                    return self.resume(Some(1));
+                },
+                "c" | "continue" => return self.resume(None),
+                "u" | "until" => match arg.and_then(parse_addr) {
+                    Some(addr) => {
+                        self.run_to = Some(addr);
+                        return self.resume(None);
+                    },
+                    None => println!("until requires an address")
+                },
+                "b" | "break" => match arg {
+                    Some(arg) => match parse_addr(arg) {
+                        Some(addr) => self.add_breakpoint(addr),
+                        None => println!("Invalid address {}", arg)
+                    },
+                    None if self.breakpoints.is_empty() => println!("No breakpoints set"),
+                    None => {
+                        for addr in self.breakpoints.iter() {
+                            println!("Breakpoint at 0x{:04X}", addr);
+                        }
+                    }
+                },
+                "d" | "delete" => match arg.and_then(parse_addr) {
+                    Some(addr) => self.breakpoints.retain(|breakpoint| *breakpoint != addr),
+                    None => println!("delete requires an address")
+                },
+                "w" | "watch" | "rw" | "rwatch" => match arg.and_then(parse_addr) {
+                    Some(addr) => {
+                        let kind = match command.starts_with('r') {
+                            true  => WatchKind::Read,
+                            false => WatchKind::Write
+                        };
+                        if !cpu.get_memory_mut().add_watchpoint(addr, kind) {
+                            println!("Too many watchpoints - remove one with dw first");
+                        }
+                    },
+                    None => println!("{} requires an address", command)
+                },
+                "dw" => match arg.and_then(parse_addr) {
+                    Some(addr) => cpu.get_memory_mut().remove_watchpoint(addr),
+                    None => println!("dw requires an address")
+                },
+                "r" | "regs" => self.print_registers(cpu),
+                "x" => match arg.and_then(parse_addr) {
+                    Some(addr) => {
+                        let len = args.next().and_then(|n| n.parse::<u16>().ok()).unwrap_or(16);
+                        self.print_memory(cpu, addr, len);
+                    },
+                    None => println!("x requires an address")
+                },
+                "screen" => self.print_screen(cpu),
+                "h" | "help" => println!("{}", HELP),
+                "q" | "quit" => return false,
+                _ => println!("Unknown command {} - type help for a list of commands", command)
+            }
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        if !self.breakpoints.contains(&addr) {
+            self.breakpoints.push(addr);
+        }
+    }
+
+    fn resume(&mut self, steps: Option<usize>) -> bool {
+        // The step which resumes execution is the first one counted
+        self.steps = steps.map(|n| n - 1);
+        self.resuming = true;
+        true
+    }
+
+    fn get_instruction(&self, cpu: &Cpu, addr: u16) -> Option<Instruction> {
+        let opcode = self.get_word(cpu, addr)?;
+        let operand = match Instruction::has_operand(opcode) {
+            true  => self.get_word(cpu, addr.checked_add(2)?)?,
+            false => 0
+        };
+        Instruction::decode(opcode, operand)
+    }
+
+    fn get_word(&self, cpu: &Cpu, addr: u16) -> Option<u16> {
+        let memory = cpu.get_memory();
+        let hi_byte = memory.peek(addr).ok()? as u16;
+        let lo_byte = memory.peek(addr.checked_add(1)?).ok()? as u16;
+        Some((hi_byte << 8) | lo_byte)
+    }
+
+    fn get_current_line(&self, cpu: &Cpu) -> Line {
+        let pc = cpu.get_program_counter();
+        let memory = cpu.get_memory();
+        let size = self.get_instruction(cpu, pc).map(|instr| instr.get_size()).unwrap_or(2);
+        let bytes: Vec<u8> = (pc..pc.saturating_add(size)).filter_map(|addr| memory.peek(addr).ok()).collect();
+
+        match self.get_instruction(cpu, pc) {
+            Some(instr) if bytes.len() == size as usize => Line::Code { addr: pc, bytes, instr },
+            _ => Line::Data { addr: pc, bytes }
+        }
+    }
+
+    fn print_registers(&self, cpu: &Cpu) {
+        println!(
+            "PC: 0x{:04X}  I: 0x{:04X}  DT: {:3}  ST: {:3}  SP: {}",
+            cpu.get_program_counter(),
+            cpu.get_i_register(),
+            cpu.get_delay_timer(),
+            cpu.get_sound_timer(),
+            cpu.get_stack().len()
+        );
+
+        for (i, value) in cpu.get_v_registers().iter().enumerate() {
+            print!("V{:X}: 0x{:02X}", i, value);
+            match i % 8 == 7 {
+                true  => println!(),
+                false => print!("  ")
+            }
+        }
+
+        let stack: Vec<String> = cpu.get_stack().iter().map(|addr| format!("0x{:04X}", addr)).collect();
+        println!("Stack: [{}]", stack.join(", "));
+        println!("{}", self.get_current_line(cpu));
+    }
+
+    fn print_memory(&self, cpu: &Cpu, start: u16, len: u16) {
+        let memory = cpu.get_memory();
+        let end = (start as usize + len as usize).min(memory.get_size());
+        for row_start in (start as usize..end).step_by(16) {
+            let row: Vec<String> = (row_start..end.min(row_start + 16))
+                .filter_map(|addr| memory.peek(addr as u16).ok())
+                .map(|b| format!("{:02X}", b))
+                .collect();
+            println!("0x{:04X}  {}", row_start, row.join(" "));
+        }
+    }
+
+    fn print_screen(&self, cpu: &Cpu) {
+        for y in 0..cpu.get_display_height() {
+            let row: String = (0..cpu.get_display_width())
+                .map(|x| match cpu.get_display_pixel(x, y) {
+                    0 => '.',
+                    _ => '#'
+                })
+                .collect();
+            println!("{}", row);
+        }
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/**
+ * Parse an address in hex, with or without a 0x prefix
+ */
+fn parse_addr(arg: &str) -> Option<u16> {
+    let digits = arg.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).ok()
+}
@@ -0,0 +1,135 @@
+use std::fmt;
+
+use crate::{instruction::Instruction, rom::Rom, variant::Variant};
+
+// Programs are loaded at, and start executing from, 0x200
+const PROGRAM_START: u16 = 0x200;
+
+#[derive(Debug)]
+pub enum Line {
+    Code { addr: u16, bytes: Vec<u8>, instr: Instruction },
+    Data { addr: u16, bytes: Vec<u8> },
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (addr, bytes) = match self {
+            Line::Code { addr, bytes, .. } | Line::Data { addr, bytes } => (addr, bytes)
+        };
+        let raw: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        write!(f, "0x{:04X}  {:<12}", addr, raw.join(" "))?;
+
+        match self {
+            Line::Code { instr, .. } => write!(f, "{}", instr),
+            Line::Data { bytes, .. } => {
+                let data: Vec<String> = bytes.iter().map(|b| format!("0x{:02X}", b)).collect();
+                write!(f, "DB {}", data.join(", "))
+            }
+        }
+    }
+}
+
+/**
+ * Disassemble a ROM by following every path of execution from the start of the program, so that
+ * anything which is never executed (sprites, lookup tables etc.) is shown as data
+ */
+pub fn disassemble(rom: &Rom, variant: Variant) -> Vec<Line> {
+    let end = PROGRAM_START as usize + rom.get_size();
+    let is_code = trace(rom, variant);
+
+    let mut lines = Vec::new();
+    let mut addr = PROGRAM_START as usize;
+    while addr < end {
+        match is_code[addr - PROGRAM_START as usize] {
+            Some(instr) => {
+                let size = instr.get_size() as usize;
+                lines.push(Line::Code { addr: addr as u16, bytes: get_bytes(rom, addr, size), instr });
+                addr += size;
+            },
+            None => {
+                // Group data into words, unless the second byte is the start of an instruction
+                let size = match addr + 1 < end && is_code[addr + 1 - PROGRAM_START as usize].is_none() {
+                    true  => 2,
+                    false => 1
+                };
+                lines.push(Line::Data { addr: addr as u16, bytes: get_bytes(rom, addr, size) });
+                addr += size;
+            }
+        }
+    }
+
+    lines
+}
+
+/**
+ * Return the instruction starting at each byte of the ROM, or None for bytes which aren't the start
+ * of any reachable instruction
+ */
+fn trace(rom: &Rom, variant: Variant) -> Vec<Option<Instruction>> {
+    let mut is_code = vec![None; rom.get_size()];
+    let mut pending = vec![PROGRAM_START];
+
+    while let Some(addr) = pending.pop() {
+        let instr = match decode_at(rom, variant, addr) {
+            Some(instr) => instr,
+            None => continue
+        };
+
+        let offset = (addr - PROGRAM_START) as usize;
+        if is_code[offset].is_some() {
+            continue;
+        }
+        is_code[offset] = Some(instr);
+
+        let next = addr + instr.get_size();
+        match instr {
+            Instruction::Jump { addr } => pending.push(addr),
+            Instruction::Call { addr } => {
+                pending.push(addr);
+                pending.push(next);
+            },
+            // The target of BNNN depends on a register, so can't be followed
+            Instruction::Return | Instruction::Exit | Instruction::JumpWithOffset { .. } => (),
+            Instruction::SkipIfEqual { .. } |
+            Instruction::SkipIfNotEqual { .. } |
+            Instruction::SkipIfRegistersEqual { .. } |
+            Instruction::SkipIfRegistersNotEqual { .. } |
+            Instruction::SkipIfKeyPressed { .. } |
+            Instruction::SkipIfKeyNotPressed { .. } => {
+                pending.push(next);
+                if let Some(skipped) = decode_at(rom, variant, next) {
+                    pending.push(next + skipped.get_size());
+                }
+            },
+            _ => pending.push(next)
+        }
+    }
+
+    is_code
+}
+
+fn decode_at(rom: &Rom, variant: Variant, addr: u16) -> Option<Instruction> {
+    let offset = addr.checked_sub(PROGRAM_START)? as usize;
+    if offset + 1 >= rom.get_size() {
+        return None;
+    }
+
+    let opcode = get_word(rom, offset);
+    let operand = match Instruction::has_operand(opcode) && offset + 3 < rom.get_size() {
+        true  => get_word(rom, offset + 2),
+        false => 0
+    };
+    match Instruction::decode(opcode, operand) {
+        Some(instr) if instr.get_variant() <= variant && offset + instr.get_size() as usize <= rom.get_size() => Some(instr),
+        _ => None
+    }
+}
+
+fn get_word(rom: &Rom, offset: usize) -> u16 {
+    ((rom.get_byte(offset) as u16) << 8) | rom.get_byte(offset + 1) as u16
+}
+
+fn get_bytes(rom: &Rom, addr: usize, size: usize) -> Vec<u8> {
+    let offset = addr - PROGRAM_START as usize;
+    (offset..offset + size).map(|i| rom.get_byte(i)).collect()
+}
@@ -1,42 +1,170 @@
-extern crate sdl2;
+#[cfg(feature = "std")]
+use crate::{error::EmulatorError, savestate::{StateReader, StateWriter}};
+use crate::util::{DISPLAY_HEIGHT, DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT, HIRES_DISPLAY_WIDTH};
 
-use crate::{util::DISPLAY_HEIGHT, util::DISPLAY_WIDTH};
-
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Display {
-    // sdl_context: sdl2::Sdl,
-    screen: [[u8; DISPLAY_HEIGHT]; DISPLAY_WIDTH]
+    // The screen is always sized for high resolution mode - in low resolution mode only the
+    // top left DISPLAY_WIDTH x DISPLAY_HEIGHT pixels are used
+    // Each pixel holds one bit per bitplane, giving a colour index from 0 to 3
+    screen: [[u8; HIRES_DISPLAY_HEIGHT]; HIRES_DISPLAY_WIDTH],
+    hires: bool,
+    selected_planes: u8, // Bitmask of the planes affected by drawing, clearing and scrolling
     // screen: Vec<u8>
 }
 
 impl Display {
-    pub fn new() -> Self {
+    pub const fn new() -> Self {
         Display {
-            // sdl_context: sdl2::init().unwrap(),
-            screen: [[0; DISPLAY_HEIGHT]; DISPLAY_WIDTH]
+            screen: [[0; HIRES_DISPLAY_HEIGHT]; HIRES_DISPLAY_WIDTH],
+            hires: false,
+            selected_planes: 0x1,
             // screen: vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT]
         }
     }
 
+    pub fn get_width(&self) -> usize {
+        match self.hires {
+            true  => HIRES_DISPLAY_WIDTH,
+            false => DISPLAY_WIDTH
+        }
+    }
+
+    pub fn get_height(&self) -> usize {
+        match self.hires {
+            true  => HIRES_DISPLAY_HEIGHT,
+            false => DISPLAY_HEIGHT
+        }
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /**
+     * Switch between high (128x64) and low (64x32) resolution. Switching modes clears every plane
+     */
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.screen = [[0; HIRES_DISPLAY_HEIGHT]; HIRES_DISPLAY_WIDTH];
+    }
+
+    pub fn get_selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
+
     /**
+     * Select which bitplanes (XO-CHIP FN01) subsequent drawing, clearing and scrolling affect
+     */
+    pub fn select_planes(&mut self, planes: u8) {
+        self.selected_planes = planes & 0x3;
+    }
+
+    /**
+     * XOR the pixel in the given plane with val.
      * Return true if the value was changed from set to unset in the display, false otherwise
      */
-    pub fn set_pixel(&mut self, x: usize, y: usize, val: u8) -> bool {
-        let current_val = self.screen[x][y];
-        self.screen[x][y] ^= val;
-        current_val == 1 && self.screen[x][y] == 0
+    pub fn set_pixel(&mut self, x: usize, y: usize, plane: u8, val: u8) -> bool {
+        let current_val = self.screen[x][y] & plane;
+        if val > 0 {
+            self.screen[x][y] ^= plane;
+        }
+        current_val != 0 && val > 0
     }
 
+    /**
+     * Return the colour index of the pixel - bit 0 is set by the first plane, bit 1 by the second
+     */
     pub fn get_pixel(&self, x: usize, y: usize) -> u8 {
         // self.screen[x * y]
         self.screen[x][y]
     }
 
-    pub fn get_screen_state(&self) -> &[[u8; DISPLAY_HEIGHT]; DISPLAY_WIDTH] {
+    pub fn get_screen_state(&self) -> &[[u8; HIRES_DISPLAY_HEIGHT]; HIRES_DISPLAY_WIDTH] {
         &self.screen
     }
 
     pub fn clear_screen(&mut self) {
-        self.screen = [[0; DISPLAY_HEIGHT]; DISPLAY_WIDTH]
+        for column in self.screen.iter_mut() {
+            for pixel in column.iter_mut() {
+                *pixel &= !self.selected_planes;
+            }
+        }
+    }
+
+    pub fn scroll_up(&mut self, rows: usize) {
+        let (width, height) = (self.get_width(), self.get_height());
+        for x in 0..width {
+            for y in 0..height {
+                let moved = match y + rows < height {
+                    true  => self.screen[x][y + rows],
+                    false => 0
+                };
+                self.screen[x][y] = self.merge_selected(self.screen[x][y], moved);
+            }
+        }
+    }
+
+    pub fn scroll_down(&mut self, rows: usize) {
+        let (width, height) = (self.get_width(), self.get_height());
+        for x in 0..width {
+            for y in (0..height).rev() {
+                let moved = match y >= rows {
+                    true  => self.screen[x][y - rows],
+                    false => 0
+                };
+                self.screen[x][y] = self.merge_selected(self.screen[x][y], moved);
+            }
+        }
+    }
+
+    pub fn scroll_left(&mut self, cols: usize) {
+        let (width, height) = (self.get_width(), self.get_height());
+        for x in 0..width {
+            for y in 0..height {
+                let moved = match x + cols < width {
+                    true  => self.screen[x + cols][y],
+                    false => 0
+                };
+                self.screen[x][y] = self.merge_selected(self.screen[x][y], moved);
+            }
+        }
+    }
+
+    pub fn scroll_right(&mut self, cols: usize) {
+        let (width, height) = (self.get_width(), self.get_height());
+        for x in (0..width).rev() {
+            for y in 0..height {
+                let moved = match x >= cols {
+                    true  => self.screen[x - cols][y],
+                    false => 0
+                };
+                self.screen[x][y] = self.merge_selected(self.screen[x][y], moved);
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.hires);
+        writer.write_u8(self.selected_planes);
+        for column in self.screen.iter() {
+            writer.write_bytes(column);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), EmulatorError> {
+        self.hires = reader.read_bool()?;
+        self.selected_planes = reader.read_u8()? & 0x3;
+        for column in self.screen.iter_mut() {
+            column.copy_from_slice(reader.read_bytes(HIRES_DISPLAY_HEIGHT)?);
+        }
+        Ok(())
+    }
+
+    fn merge_selected(&self, current: u8, moved: u8) -> u8 {
+        // Scrolling only moves the selected planes, the others are left where they are
+        (current & !self.selected_planes) | (moved & self.selected_planes)
     }
 }
@@ -0,0 +1,63 @@
+use core::fmt;
+
+#[cfg(feature = "std")]
+use crate::{assembler::AssemblerError, savestate::SAVE_STATE_VERSION};
+
+/**
+ * Errors raised while running a program. These are problems with the program being emulated
+ * (or the state it has got itself into), rather than bugs in the emulator itself. Errors from
+ * loading files only exist with the std feature, so matches need a wildcard arm to build with or
+ * without it
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EmulatorError {
+    UnknownOpcode { pc: u16, opcode: u16 },
+    StackUnderflow { pc: u16 },
+    StackOverflow { pc: u16 },
+    MemoryOutOfBounds { addr: u16 },
+    InvalidKey { key: u8 },
+    #[cfg(feature = "std")]
+    Assembler(AssemblerError),
+    #[cfg(feature = "std")]
+    Io(String), // Reading a file failed, with the reason
+    InvalidSaveState { reason: &'static str },
+    #[cfg(feature = "std")]
+    UnsupportedSaveStateVersion { version: u16 },
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmulatorError::UnknownOpcode { pc, opcode } => write!(f, "Operation not found - 0x{:04X} at PC 0x{:04X}", opcode, pc),
+            EmulatorError::StackUnderflow { pc }        => write!(f, "Returned from subroutine with an empty stack at PC 0x{:04X}", pc),
+            EmulatorError::StackOverflow { pc }         => write!(f, "Stack overflowed calling subroutine at PC 0x{:04X}", pc),
+            EmulatorError::MemoryOutOfBounds { addr }   => write!(f, "Memory address out of bounds - 0x{:04X}", addr),
+            EmulatorError::InvalidKey { key }           => write!(f, "Key out of range - 0x{:02X}, expected 0x0 to 0xF", key),
+            #[cfg(feature = "std")]
+            EmulatorError::Assembler(e)                 => write!(f, "Assembly failed - {}", e),
+            #[cfg(feature = "std")]
+            EmulatorError::Io(reason)                   => write!(f, "{}", reason),
+            EmulatorError::InvalidSaveState { reason }  => write!(f, "Invalid save state - {}", reason),
+            #[cfg(feature = "std")]
+            EmulatorError::UnsupportedSaveStateVersion { version } => write!(f, "Save state version {} is not supported - expected version {}", version, SAVE_STATE_VERSION),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EmulatorError {}
+
+#[cfg(feature = "std")]
+impl From<AssemblerError> for EmulatorError {
+    fn from(e: AssemblerError) -> Self {
+        EmulatorError::Assembler(e)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for EmulatorError {
+    fn from(e: std::io::Error) -> Self {
+        EmulatorError::Io(e.to_string())
+    }
+}
@@ -0,0 +1,130 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{display::Display, palette::Palette};
+
+/**
+ * Input from the host, already translated from whatever keys or buttons it uses
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    KeyDown(u8),     // CHIP-8 key 0-F pressed
+    KeyUp(u8),       // CHIP-8 key 0-F released
+    SaveState(u8),   // Save to a numbered slot
+    LoadState(u8),   // Load from a numbered slot
+    RewindStart,
+    RewindStop,
+    Debug,           // Pause in the debugger
+    ToggleMute,
+    Screenshot,      // Save the screen as it's shown to an image
+    ToggleVideo,     // Start or stop recording a video of the screen
+    Quit,
+}
+
+/**
+ * Somewhere to show the display at the end of each frame
+ */
+pub trait VideoSink {
+    fn present(&mut self, display: &Display);
+
+    /**
+     * Colours the display is shown in, which screenshots are taken in too
+     */
+    fn get_palette(&self) -> Palette {
+        Palette::default()
+    }
+
+    /**
+     * How many times larger than the display each pixel is shown, which screenshots are scaled by
+     */
+    fn get_scale(&self, _display: &Display) -> usize {
+        1
+    }
+
+    /**
+     * Show a status message, e.g. that a state was saved. Printed to stderr unless the frontend has
+     * somewhere better to put it
+     */
+    fn show_message(&mut self, message: &str) {
+        eprintln!("{}", message);
+    }
+}
+
+/**
+ * Source of input events, polled once at the start of each frame
+ */
+pub trait InputSource {
+    fn poll_events(&mut self) -> Vec<InputEvent>;
+}
+
+/**
+ * Plays the buzzer while the sound timer is running
+ */
+pub trait AudioSink {
+    fn set_playing(&mut self, playing: bool);
+}
+
+/**
+ * Paces the emulator, blocking until it is time to run the next frame
+ */
+pub trait Clock {
+    fn wait_for_frame(&mut self);
+}
+
+/**
+ * Audio sink for hosts without sound
+ */
+#[derive(Debug)]
+pub struct NullAudio;
+
+impl AudioSink for NullAudio {
+    fn set_playing(&mut self, _playing: bool) {}
+}
+
+/**
+ * Clock which never waits, running frames as fast as possible
+ */
+#[derive(Debug)]
+pub struct UncappedClock;
+
+impl Clock for UncappedClock {
+    fn wait_for_frame(&mut self) {}
+}
+
+/**
+ * Clock which runs frames at a fixed rate in real time, however long presenting them takes. If the
+ * host falls behind, frames are run without waiting until it catches up - unless it's so far behind
+ * (e.g. after sitting in the debugger) that it's better to just carry on from now
+ */
+#[derive(Debug)]
+pub struct WallClock {
+    frame_duration: Duration,
+    next_frame: Option<Instant>, // None until the first frame
+}
+
+// How far behind the clock can get before giving up on catching up
+const MAX_LAG: Duration = Duration::from_millis(250);
+
+impl WallClock {
+    pub fn new(frames_per_second: u32) -> Self {
+        Self { frame_duration: Duration::from_secs(1) / frames_per_second, next_frame: None }
+    }
+}
+
+impl Clock for WallClock {
+    fn wait_for_frame(&mut self) {
+        let now = Instant::now();
+        let next_frame = self.next_frame.unwrap_or(now);
+
+        let next_frame = match next_frame.checked_duration_since(now) {
+            Some(wait) => {
+                thread::sleep(wait);
+                next_frame
+            },
+            None if now - next_frame > MAX_LAG => now,
+            None => next_frame
+        };
+
+        self.next_frame = Some(next_frame + self.frame_duration);
+    }
+}
@@ -0,0 +1,511 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::cpu::Cpu;
+
+// Register numbers as seen by GDB - multi-byte registers are sent little endian
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_DT: usize = 18;
+const REG_ST: usize = 19;
+const REG_SP: usize = 20;
+const REG_COUNT: usize = 21;
+
+// Signal numbers reported to GDB when execution stops
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+// Byte sent by GDB to interrupt a running target
+const INTERRUPT: u8 = 0x03;
+
+enum Response {
+    Reply(String),
+    Resume,
+    Detach,
+    Kill,
+}
+
+/**
+ * A GDB remote serial protocol server, which lets standard GDB front-ends attach to the CPU over TCP.
+ * GDB has no CHIP-8 architecture of its own, so use gdb-multiarch and connect without a program
+ * file, with `target remote localhost:PORT`. The registers are described to GDB in target.xml, and
+ * registers, memory, software breakpoints and single stepping are supported, but GDB can't
+ * disassemble CHIP-8 - use the built in debugger or `disasm` for that
+ */
+#[derive(Debug)]
+pub struct GdbStub {
+    stream: TcpStream,
+    breakpoints: Vec<u16>,
+    stepping: bool,     // Stop again after the next instruction
+    running: bool,      // GDB is waiting for a stop reply
+    attached: bool,
+    last_packet: String, // Sent again if GDB reports a bad checksum
+}
+
+impl GdbStub {
+
+    /**
+     * Wait for a GDB client to connect on the given local port. The program starts stopped so it
+     * can be inspected before anything runs
+     */
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        eprintln!("Waiting for GDB to connect on port {}...", port);
+        let (stream, addr) = listener.accept()?;
+        eprintln!("GDB connected from {}", addr);
+        GdbStub::from_stream(stream)
+    }
+
+    fn from_stream(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            breakpoints: Vec::new(),
+            stepping: true,
+            running: false,
+            attached: true,
+            last_packet: String::new()
+        })
+    }
+
+    /**
+     * Check whether execution should stop before the CPU's next instruction
+     */
+    pub fn should_break(&mut self, cpu: &Cpu) -> bool {
+        if !self.attached || cpu.is_waiting_for_vblank() {
+            return false;
+        }
+
+        if self.check_interrupt() {
+            self.send_stop(SIGINT);
+            return true;
+        }
+
+        if self.stepping || self.breakpoints.contains(&cpu.get_program_counter()) {
+            self.send_stop(SIGTRAP);
+            return true;
+        }
+
+        false
+    }
+
+    /**
+     * Handle packets from GDB until it resumes execution. Returns false if GDB killed the program
+     */
+    pub fn serve(&mut self, cpu: &mut Cpu) -> bool {
+        loop {
+            let packet = match self.read_packet() {
+                Ok(Some(packet)) => packet,
+                // Already stopped, so just tell GDB where we are
+                Ok(None) => {
+                    self.running = true;
+                    self.send_stop(SIGINT);
+                    continue;
+                },
+                Err(e) => {
+                    eprintln!("GDB connection lost: {}", e);
+                    self.attached = false;
+                    return true;
+                }
+            };
+
+            match self.handle_packet(&packet, cpu) {
+                Response::Reply(reply) => {
+                    if self.send_packet(&reply).is_err() {
+                        self.attached = false;
+                        return true;
+                    }
+                },
+                Response::Resume => {
+                    self.running = true;
+                    return true;
+                },
+                Response::Detach => {
+                    let _ = self.send_packet("OK");
+                    self.attached = false;
+                    return true;
+                },
+                Response::Kill => return false
+            }
+        }
+    }
+
+    /**
+     * Let GDB know that the program has exited the interpreter
+     */
+    pub fn exited(&mut self) {
+        if self.attached {
+            let _ = self.send_packet("W00");
+            self.attached = false;
+        }
+    }
+
+    fn handle_packet(&mut self, packet: &str, cpu: &mut Cpu) -> Response {
+        if packet.is_empty() || !packet.is_char_boundary(1) {
+            return Response::Reply(String::new());
+        }
+
+        let (command, args) = packet.split_at(1);
+        let reply = match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => (0..REG_COUNT).map(|reg| encode_hex(&self.read_register(cpu, reg))).collect(),
+            "G" => match decode_hex(args) {
+                Some(bytes) => {
+                    let mut offset = 0;
+                    for reg in 0..REG_COUNT {
+                        let size = get_register_size(reg);
+                        if let Some(value) = bytes.get(offset..offset + size) {
+                            self.write_register(cpu, reg, value);
+                        }
+                        offset += size;
+                    }
+                    "OK".to_string()
+                },
+                None => "E01".to_string()
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(reg) if reg < REG_COUNT => encode_hex(&self.read_register(cpu, reg)),
+                _ => "E01".to_string()
+            },
+            "P" => {
+                let (reg, value) = args.split_once('=').unwrap_or((args, ""));
+                match (usize::from_str_radix(reg, 16), decode_hex(value)) {
+                    (Ok(reg), Some(value)) if reg < REG_COUNT && value.len() == get_register_size(reg) => {
+                        self.write_register(cpu, reg, &value);
+                        "OK".to_string()
+                    },
+                    _ => "E01".to_string()
+                }
+            },
+            "m" => match parse_addr_len(args) {
+                Some((addr, len)) => {
+                    let memory = cpu.get_memory();
+                    let bytes: Vec<u8> = (0..len)
+                        .map_while(|i| addr.checked_add(i).and_then(|addr| memory.peek(addr).ok()))
+                        .collect();
+                    match bytes.is_empty() && len > 0 {
+                        true  => "E01".to_string(),
+                        false => encode_hex(&bytes)
+                    }
+                },
+                None => "E01".to_string()
+            },
+            "M" => {
+                let (target, data) = args.split_once(':').unwrap_or((args, ""));
+                match (parse_addr_len(target), decode_hex(data)) {
+                    (Some((addr, len)), Some(bytes)) if bytes.len() == len as usize => {
+                        let memory = cpu.get_memory_mut();
+                        let written = bytes.iter().enumerate().all(|(i, byte)| {
+                            addr.checked_add(i as u16).map(|addr| memory.poke(addr, *byte).is_ok()).unwrap_or(false)
+                        });
+                        match written {
+                            true  => "OK".to_string(),
+                            false => "E01".to_string()
+                        }
+                    },
+                    _ => "E01".to_string()
+                }
+            },
+            // Software breakpoints only - hardware breakpoints and watchpoints are left to the built-in debugger
+            "Z" | "z" => match args.strip_prefix("0,").and_then(parse_addr_len) {
+                Some((addr, _)) => {
+                    match command == "Z" {
+                        true  => if !self.breakpoints.contains(&addr) { self.breakpoints.push(addr) },
+                        false => self.breakpoints.retain(|breakpoint| *breakpoint != addr)
+                    }
+                    "OK".to_string()
+                },
+                None => String::new()
+            },
+            "c" | "s" => {
+                if let Ok(addr) = u16::from_str_radix(args, 16) {
+                    cpu.set_program_counter(addr);
+                }
+                self.stepping = command == "s";
+                return Response::Resume;
+            },
+            "D" => return Response::Detach,
+            "k" => return Response::Kill,
+            "H" => "OK".to_string(),
+            "q" => self.handle_query(packet),
+            // Anything else is unsupported, which GDB expects to be signalled with an empty reply
+            _ => String::new()
+        };
+
+        Response::Reply(reply)
+    }
+
+    fn handle_query(&self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return "PacketSize=1000;qXfer:features:read+".to_string();
+        }
+
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let target = get_target_description();
+            return match parse_addr_len(range) {
+                Some((offset, len)) => {
+                    let start = (offset as usize).min(target.len());
+                    let end = (start + len as usize).min(target.len());
+                    match end == target.len() {
+                        true  => format!("l{}", &target[start..end]),
+                        false => format!("m{}", &target[start..end])
+                    }
+                },
+                None => "E01".to_string()
+            };
+        }
+
+        match packet {
+            "qAttached" => "1".to_string(),
+            "qC"        => "QC1".to_string(),
+            _           => String::new()
+        }
+    }
+
+    fn read_register(&self, cpu: &Cpu, reg: usize) -> Vec<u8> {
+        match reg {
+            REG_I  => cpu.get_i_register().to_le_bytes().to_vec(),
+            REG_PC => cpu.get_program_counter().to_le_bytes().to_vec(),
+            REG_DT => vec![cpu.get_delay_timer()],
+            REG_ST => vec![cpu.get_sound_timer()],
+            REG_SP => vec![cpu.get_stack().len() as u8],
+            _      => vec![cpu.get_v_registers()[reg]]
+        }
+    }
+
+    fn write_register(&self, cpu: &mut Cpu, reg: usize, value: &[u8]) {
+        match reg {
+            REG_I  => cpu.set_i_register(u16::from_le_bytes([value[0], value[1]])),
+            REG_PC => cpu.set_program_counter(u16::from_le_bytes([value[0], value[1]])),
+            REG_DT => cpu.set_delay_timer(value[0]),
+            REG_ST => cpu.set_sound_timer(value[0]),
+            // The stack pointer is reported for information, but the stack itself can't be edited
+            REG_SP => (),
+            _      => cpu.set_v_register(reg, value[0])
+        }
+    }
+
+    /**
+     * Check, without blocking, whether GDB has asked to interrupt the running program
+     */
+    fn check_interrupt(&mut self) -> bool {
+        let mut byte = [0u8];
+        if self.stream.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let interrupted = matches!(self.stream.peek(&mut byte), Ok(1) if byte[0] == INTERRUPT);
+        if interrupted {
+            let _ = self.stream.read_exact(&mut byte);
+        }
+        let _ = self.stream.set_nonblocking(false);
+        interrupted
+    }
+
+    fn send_stop(&mut self, signal: u8) {
+        // A stop reply is only expected if GDB resumed the program
+        if self.running {
+            self.running = false;
+            if self.send_packet(&format!("S{:02x}", signal)).is_err() {
+                self.attached = false;
+            }
+        }
+    }
+
+    /**
+     * Read the next packet, acknowledging it. Returns None if GDB sent an interrupt instead
+     */
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                b'$' => (),
+                INTERRUPT => return Ok(None),
+                b'-' => {
+                    let packet = self.last_packet.clone();
+                    self.send_packet(&packet)?;
+                    continue;
+                },
+                // Acknowledgements for our own packets, or line noise
+                _ => continue
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    byte => data.push(byte)
+                }
+            }
+
+            let checksum = [self.read_byte()?, self.read_byte()?];
+            let expected = std::str::from_utf8(&checksum).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            match expected == Some(get_checksum(&data)) {
+                true  => {
+                    self.stream.write_all(b"+")?;
+                    return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+                },
+                false => self.stream.write_all(b"-")?
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0u8];
+        self.stream.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        self.last_packet = data.to_string();
+        let packet = format!("${}#{:02x}", data, get_checksum(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes())?;
+        self.stream.flush()
+    }
+}
+
+fn get_register_size(reg: usize) -> usize {
+    match reg {
+        REG_I | REG_PC => 2,
+        _              => 1
+    }
+}
+
+/**
+ * Describe the CHIP-8 registers to GDB, which has no built in knowledge of the architecture
+ */
+fn get_target_description() -> String {
+    let mut registers = String::new();
+    for i in 0..16 {
+        registers.push_str(&format!("<reg name=\"v{:x}\" bitsize=\"8\" type=\"uint8\"/>", i));
+    }
+    registers.push_str("<reg name=\"i\" bitsize=\"16\" type=\"data_ptr\"/>");
+    registers.push_str("<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>");
+    registers.push_str("<reg name=\"dt\" bitsize=\"8\" type=\"uint8\"/>");
+    registers.push_str("<reg name=\"st\" bitsize=\"8\" type=\"uint8\"/>");
+    registers.push_str("<reg name=\"sp\" bitsize=\"8\" type=\"uint8\"/>");
+
+    format!(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\"><target version=\"1.0\"><architecture>chip8</architecture><feature name=\"org.rusty-chip.chip8\">{}</feature></target>",
+        registers
+    )
+}
+
+fn get_checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/**
+ * Parse the `addr,length` arguments used by several packets
+ */
+fn parse_addr_len(args: &str) -> Option<(u16, u16)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((u16::from_str_radix(addr, 16).ok()?, u16::from_str_radix(len, 16).ok()?))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{display::Display, keyboard::Keyboard, memory::Memory, quirks::Quirks, rng::Rng, variant::Variant};
+
+    /**
+     * A stub connected to a client socket standing in for GDB
+     */
+    fn connect() -> (GdbStub, TcpStream) {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        (GdbStub::from_stream(stream).unwrap(), client)
+    }
+
+    fn get_cpu() -> Cpu {
+        let variant = Variant::Chip8;
+        let mut cpu = Cpu::new(Memory::new(variant), Display::new(), Keyboard::new(), Rng::new(0), variant, Quirks::for_variant(variant));
+        cpu.load_program(&[0x60, 0x12, 0xA3, 0x00]).unwrap();
+        cpu
+    }
+
+    fn reply(stub: &mut GdbStub, cpu: &mut Cpu, packet: &str) -> String {
+        match stub.handle_packet(packet, cpu) {
+            Response::Reply(reply) => reply,
+            _ => panic!("Expected a reply to {}", packet)
+        }
+    }
+
+    #[test]
+    fn reads_packets_with_valid_checksums() {
+        let (mut stub, mut client) = connect();
+        client.write_all(b"$g#67").unwrap();
+        assert_eq!(stub.read_packet().unwrap(), Some("g".to_string()));
+
+        let mut ack = [0u8];
+        client.read_exact(&mut ack).unwrap();
+        assert_eq!(&ack, b"+");
+    }
+
+    #[test]
+    fn rejects_packets_with_bad_checksums() {
+        let (mut stub, mut client) = connect();
+        client.write_all(b"$g#00$?#3f").unwrap();
+        assert_eq!(stub.read_packet().unwrap(), Some("?".to_string()));
+
+        let mut acks = [0u8; 2];
+        client.read_exact(&mut acks).unwrap();
+        assert_eq!(&acks, b"-+");
+    }
+
+    #[test]
+    fn sends_packets_with_checksums() {
+        let (mut stub, mut client) = connect();
+        stub.send_packet("OK").unwrap();
+
+        let mut packet = [0u8; 6];
+        client.read_exact(&mut packet).unwrap();
+        assert_eq!(&packet, b"$OK#9a");
+    }
+
+    #[test]
+    fn replies_with_registers() {
+        let (mut stub, _client) = connect();
+        let mut cpu = get_cpu();
+        cpu.set_v_register(0x1, 0xAB);
+        cpu.set_i_register(0x0345);
+
+        // V0-VF, then I and PC little endian, then DT, ST and SP
+        let expected = format!("00ab{}45030002000000", "00".repeat(14));
+        assert_eq!(reply(&mut stub, &mut cpu, "g"), expected);
+    }
+
+    #[test]
+    fn replies_with_memory() {
+        let (mut stub, _client) = connect();
+        let mut cpu = get_cpu();
+        assert_eq!(reply(&mut stub, &mut cpu, "m200,4"), "6012a300");
+        assert_eq!(reply(&mut stub, &mut cpu, "mffff,1"), "E01");
+    }
+
+    #[test]
+    fn sets_and_clears_breakpoints() {
+        let (mut stub, _client) = connect();
+        let mut cpu = get_cpu();
+        assert_eq!(reply(&mut stub, &mut cpu, "Z0,202,2"), "OK");
+        assert_eq!(stub.breakpoints, vec![0x202]);
+        assert_eq!(reply(&mut stub, &mut cpu, "z0,202,2"), "OK");
+        assert!(stub.breakpoints.is_empty());
+
+        // Hardware breakpoints aren't supported
+        assert_eq!(reply(&mut stub, &mut cpu, "Z1,202,2"), "");
+    }
+}
@@ -0,0 +1,180 @@
+use std::fs;
+
+use crate::{chip8::Chip8, display::Display, error::EmulatorError, palette::Palette, screenshot::{encode_pbm, save_screenshot, ImageFormat}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    Ascii, // '#' for lit pixels and '.' for unlit ones
+    Pbm,   // Netpbm bitmap, readable by most image tools
+}
+
+impl DumpFormat {
+    pub fn from_name(name: &str) -> Option<DumpFormat> {
+        match name.to_lowercase().as_str() {
+            "ascii" => Some(DumpFormat::Ascii),
+            "pbm"   => Some(DumpFormat::Pbm),
+            _       => None
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HeadlessOptions {
+    pub max_frames: u64,
+    pub stable_frames: Option<u64>, // Stop once the screen hasn't changed for this many frames
+    pub dump_format: DumpFormat,
+    pub dump_file: Option<String>,  // Where to write the final screen - stdout if not given
+    pub screenshot_file: Option<String>, // Image to save the final screen to, as PNG or PBM depending on its extension
+    pub screenshot_scale: usize,
+    pub palette: Palette,
+}
+
+/**
+ * Why a headless run finished
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeadlessOutcome {
+    Exited,     // The program exited the interpreter with 00FD
+    Stopped,    // The stop condition set on the emulator was met
+    Stable,     // The screen stopped changing
+    FrameLimit, // max_frames ran without anything else happening
+}
+
+/**
+ * Run a program without a window until it finishes, a stop condition is met or the frame limit is
+ * reached, then dump the screen. The screen is dumped even if emulation fails, as it's often the
+ * best clue to what went wrong
+ */
+pub fn run_headless(chip8: &mut Chip8, options: &HeadlessOptions) -> Result<HeadlessOutcome, EmulatorError> {
+    let outcome = run_frames(chip8, options);
+    chip8.finish_recording();
+    chip8.stop_video();
+    print_messages(chip8);
+    dump_screen(chip8.get_display(), options);
+    if let Some(file) = &options.screenshot_file {
+        let format = ImageFormat::from_file(file).unwrap_or(ImageFormat::Png);
+        if let Err(e) = save_screenshot(chip8.get_display(), file, format, options.screenshot_scale, &options.palette) {
+            eprintln!("Failed to write screenshot to {}: {}", file, e);
+        }
+    }
+    outcome
+}
+
+fn run_frames(chip8: &mut Chip8, options: &HeadlessOptions) -> Result<HeadlessOutcome, EmulatorError> {
+    let mut previous = *chip8.get_display().get_screen_state();
+    let mut unchanged_frames = 0;
+
+    for _ in 0..options.max_frames {
+        let running = chip8.run_frame(&[])?;
+        print_messages(chip8);
+        if !running {
+            return match chip8.has_stopped() {
+                true  => Ok(HeadlessOutcome::Stopped),
+                false => Ok(HeadlessOutcome::Exited)
+            };
+        }
+
+        let screen = chip8.get_display().get_screen_state();
+        match *screen == previous {
+            true  => unchanged_frames += 1,
+            false => {
+                unchanged_frames = 0;
+                previous = *screen;
+            }
+        }
+
+        if let Some(stable_frames) = options.stable_frames {
+            if unchanged_frames >= stable_frames {
+                return Ok(HeadlessOutcome::Stable);
+            }
+        }
+    }
+
+    Ok(HeadlessOutcome::FrameLimit)
+}
+
+fn print_messages(chip8: &mut Chip8) {
+    for message in chip8.take_messages() {
+        eprintln!("{}", message);
+    }
+}
+
+fn dump_screen(display: &Display, options: &HeadlessOptions) {
+    let dump = match options.dump_format {
+        DumpFormat::Ascii => dump_ascii(display),
+        DumpFormat::Pbm   => dump_pbm(display)
+    };
+
+    match &options.dump_file {
+        Some(file) => {
+            if let Err(e) = fs::write(file, dump) {
+                eprintln!("Failed to write screen to {}: {}", file, e);
+            }
+        },
+        None => print!("{}", dump)
+    }
+}
+
+pub fn dump_ascii(display: &Display) -> String {
+    let mut dump = String::new();
+    for y in 0..display.get_height() {
+        for x in 0..display.get_width() {
+            dump.push(match display.get_pixel(x, y) {
+                0 => '.',
+                _ => '#'
+            });
+        }
+        dump.push('\n');
+    }
+    dump
+}
+
+pub fn dump_pbm(display: &Display) -> String {
+    encode_pbm(display, 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chip8::StopCondition, quirks::Quirks, variant::Variant};
+
+    fn run(program: &[u8], variant: Variant, stop_condition: Option<StopCondition>, stable_frames: Option<u64>) -> Result<HeadlessOutcome, EmulatorError> {
+        let mut chip8 = Chip8::new(variant, Quirks::for_variant(variant), 0);
+        chip8.load_rom_bytes(program).unwrap();
+        if let Some(condition) = stop_condition {
+            chip8.set_stop_condition(condition);
+        }
+
+        let options = HeadlessOptions {
+            max_frames: 10,
+            stable_frames,
+            dump_format: DumpFormat::Ascii,
+            dump_file: None,
+            screenshot_file: None,
+            screenshot_scale: 1,
+            palette: Palette::default()
+        };
+        run_headless(&mut chip8, &options)
+    }
+
+    #[test]
+    fn exits_midway_through_a_frame() {
+        // v0 := 5  i := hex v0  sprite v0 v0 5  exit, followed by nothing that could be executed
+        let program = [0x60, 0x05, 0xF0, 0x29, 0xD0, 0x05, 0x00, 0xFD];
+        assert_eq!(run(&program, Variant::SuperChip, None, None).unwrap(), HeadlessOutcome::Exited);
+    }
+
+    #[test]
+    fn stops_at_the_stop_condition() {
+        let program = [0x60, 0x05, 0x12, 0x02];
+        let outcome = run(&program, Variant::Chip8, Some(StopCondition::Address(0x202)), None);
+        assert_eq!(outcome.unwrap(), HeadlessOutcome::Stopped);
+    }
+
+    #[test]
+    fn stops_once_the_screen_is_stable() {
+        let program = [0x12, 0x00];
+        assert_eq!(run(&program, Variant::Chip8, None, Some(3)).unwrap(), HeadlessOutcome::Stable);
+        assert_eq!(run(&program, Variant::Chip8, None, None).unwrap(), HeadlessOutcome::FrameLimit);
+    }
+}
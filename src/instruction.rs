@@ -0,0 +1,309 @@
+use core::fmt;
+
+use crate::variant::Variant;
+
+/**
+ * A single decoded instruction. Register operands (x, y) are register numbers 0-F, not the values
+ * held in the registers
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    ClearScreen,                              // 00E0
+    Return,                                   // 00EE
+    ScrollDown { rows: u8 },                  // 00CN
+    ScrollUp { rows: u8 },                    // 00DN
+    ScrollRight,                              // 00FB
+    ScrollLeft,                               // 00FC
+    Exit,                                     // 00FD
+    LowRes,                                   // 00FE
+    HighRes,                                  // 00FF
+    Jump { addr: u16 },                       // 1NNN
+    Call { addr: u16 },                       // 2NNN
+    SkipIfEqual { x: u8, value: u8 },         // 3XNN
+    SkipIfNotEqual { x: u8, value: u8 },      // 4XNN
+    SkipIfRegistersEqual { x: u8, y: u8 },    // 5XY0
+    StoreRange { x: u8, y: u8 },              // 5XY2
+    LoadRange { x: u8, y: u8 },               // 5XY3
+    SetRegister { x: u8, value: u8 },         // 6XNN
+    AddValue { x: u8, value: u8 },            // 7XNN
+    Copy { x: u8, y: u8 },                    // 8XY0
+    Or { x: u8, y: u8 },                      // 8XY1
+    And { x: u8, y: u8 },                     // 8XY2
+    Xor { x: u8, y: u8 },                     // 8XY3
+    Add { x: u8, y: u8 },                     // 8XY4
+    Subtract { x: u8, y: u8 },                // 8XY5
+    ShiftRight { x: u8, y: u8 },              // 8XY6
+    SubtractReverse { x: u8, y: u8 },         // 8XY7
+    ShiftLeft { x: u8, y: u8 },               // 8XYE
+    SkipIfRegistersNotEqual { x: u8, y: u8 }, // 9XY0
+    SetI { addr: u16 },                       // ANNN
+    JumpWithOffset { addr: u16 },             // BNNN
+    Random { x: u8, mask: u8 },               // CXNN
+    Draw { x: u8, y: u8, rows: u8 },          // DXYN
+    SkipIfKeyPressed { x: u8 },               // EX9E
+    SkipIfKeyNotPressed { x: u8 },            // EXA1
+    SetILong { addr: u16 },                   // F000 NNNN - the address is the word following the opcode
+    SelectPlanes { planes: u8 },              // FN01
+    GetDelayTimer { x: u8 },                  // FX07
+    AwaitKey { x: u8 },                       // FX0A
+    SetDelayTimer { x: u8 },                  // FX15
+    SetSoundTimer { x: u8 },                  // FX18
+    AddToI { x: u8 },                         // FX1E
+    SetIToFont { x: u8 },                     // FX29
+    SetIToBigFont { x: u8 },                  // FX30
+    StoreBcd { x: u8 },                       // FX33
+    SetPitch { x: u8 },                       // FX3A
+    StoreRegisters { x: u8 },                 // FX55
+    LoadRegisters { x: u8 },                  // FX65
+    StoreFlags { x: u8 },                     // FX75
+    LoadFlags { x: u8 },                      // FX85
+}
+
+impl Instruction {
+    /**
+     * Decode an opcode, returning None if it isn't an instruction in any supported variant. operand
+     * is the word following the opcode, which is only used if `has_operand` says so
+     */
+    pub fn decode(opcode: u16, operand: u16) -> Option<Instruction> {
+        let x = ((opcode >> 8) & 0xF) as u8;
+        let y = ((opcode >> 4) & 0xF) as u8;
+        let n = (opcode & 0xF) as u8;
+        let nn = (opcode & 0xFF) as u8;
+        let nnn = opcode & 0xFFF;
+
+        let instr = match (opcode >> 12) & 0xF {
+            0x0 => match opcode {
+                0x00E0 => Instruction::ClearScreen,
+                0x00EE => Instruction::Return,
+                0x00FB => Instruction::ScrollRight,
+                0x00FC => Instruction::ScrollLeft,
+                0x00FD => Instruction::Exit,
+                0x00FE => Instruction::LowRes,
+                0x00FF => Instruction::HighRes,
+                0x00C0..=0x00CF => Instruction::ScrollDown { rows: n },
+                0x00D0..=0x00DF => Instruction::ScrollUp { rows: n },
+                _ => return None
+            },
+            0x1 => Instruction::Jump { addr: nnn },
+            0x2 => Instruction::Call { addr: nnn },
+            0x3 => Instruction::SkipIfEqual { x, value: nn },
+            0x4 => Instruction::SkipIfNotEqual { x, value: nn },
+            0x5 => match n {
+                0x0 => Instruction::SkipIfRegistersEqual { x, y },
+                0x2 => Instruction::StoreRange { x, y },
+                0x3 => Instruction::LoadRange { x, y },
+                _   => return None
+            },
+            0x6 => Instruction::SetRegister { x, value: nn },
+            0x7 => Instruction::AddValue { x, value: nn },
+            0x8 => match n {
+                0x0 => Instruction::Copy { x, y },
+                0x1 => Instruction::Or { x, y },
+                0x2 => Instruction::And { x, y },
+                0x3 => Instruction::Xor { x, y },
+                0x4 => Instruction::Add { x, y },
+                0x5 => Instruction::Subtract { x, y },
+                0x6 => Instruction::ShiftRight { x, y },
+                0x7 => Instruction::SubtractReverse { x, y },
+                0xE => Instruction::ShiftLeft { x, y },
+                _   => return None
+            },
+            0x9 if n == 0 => Instruction::SkipIfRegistersNotEqual { x, y },
+            0xA => Instruction::SetI { addr: nnn },
+            0xB => Instruction::JumpWithOffset { addr: nnn },
+            0xC => Instruction::Random { x, mask: nn },
+            0xD => Instruction::Draw { x, y, rows: n },
+            0xE => match nn {
+                0x9E => Instruction::SkipIfKeyPressed { x },
+                0xA1 => Instruction::SkipIfKeyNotPressed { x },
+                _    => return None
+            },
+            0xF => match nn {
+                0x00 if x == 0 => Instruction::SetILong { addr: operand },
+                0x01 => Instruction::SelectPlanes { planes: x },
+                0x07 => Instruction::GetDelayTimer { x },
+                0x0A => Instruction::AwaitKey { x },
+                0x15 => Instruction::SetDelayTimer { x },
+                0x18 => Instruction::SetSoundTimer { x },
+                0x1E => Instruction::AddToI { x },
+                0x29 => Instruction::SetIToFont { x },
+                0x30 => Instruction::SetIToBigFont { x },
+                0x33 => Instruction::StoreBcd { x },
+                0x3A => Instruction::SetPitch { x },
+                0x55 => Instruction::StoreRegisters { x },
+                0x65 => Instruction::LoadRegisters { x },
+                0x75 => Instruction::StoreFlags { x },
+                0x85 => Instruction::LoadFlags { x },
+                _    => return None
+            },
+            _ => return None
+        };
+
+        Some(instr)
+    }
+
+    /**
+     * Whether an opcode is followed by an operand word, as with XO-CHIP's F000 NNNN
+     */
+    pub fn has_operand(opcode: u16) -> bool {
+        opcode == 0xF000
+    }
+
+    /**
+     * The opcode for this instruction. Instructions with an operand word are followed by
+     * `encode_operand`
+     */
+    pub fn encode(&self) -> u16 {
+        let xy = |prefix: u16, x: u8, y: u8, n: u16| (prefix << 12) | ((x as u16) << 8) | ((y as u16) << 4) | n;
+        let xnn = |prefix: u16, x: u8, nn: u8| (prefix << 12) | ((x as u16) << 8) | nn as u16;
+
+        match *self {
+            Instruction::ClearScreen                      => 0x00E0,
+            Instruction::Return                           => 0x00EE,
+            Instruction::ScrollDown { rows }              => 0x00C0 | rows as u16,
+            Instruction::ScrollUp { rows }                => 0x00D0 | rows as u16,
+            Instruction::ScrollRight                      => 0x00FB,
+            Instruction::ScrollLeft                       => 0x00FC,
+            Instruction::Exit                             => 0x00FD,
+            Instruction::LowRes                           => 0x00FE,
+            Instruction::HighRes                          => 0x00FF,
+            Instruction::Jump { addr }                    => 0x1000 | addr,
+            Instruction::Call { addr }                    => 0x2000 | addr,
+            Instruction::SkipIfEqual { x, value }         => xnn(0x3, x, value),
+            Instruction::SkipIfNotEqual { x, value }      => xnn(0x4, x, value),
+            Instruction::SkipIfRegistersEqual { x, y }    => xy(0x5, x, y, 0x0),
+            Instruction::StoreRange { x, y }              => xy(0x5, x, y, 0x2),
+            Instruction::LoadRange { x, y }               => xy(0x5, x, y, 0x3),
+            Instruction::SetRegister { x, value }         => xnn(0x6, x, value),
+            Instruction::AddValue { x, value }            => xnn(0x7, x, value),
+            Instruction::Copy { x, y }                    => xy(0x8, x, y, 0x0),
+            Instruction::Or { x, y }                      => xy(0x8, x, y, 0x1),
+            Instruction::And { x, y }                     => xy(0x8, x, y, 0x2),
+            Instruction::Xor { x, y }                     => xy(0x8, x, y, 0x3),
+            Instruction::Add { x, y }                     => xy(0x8, x, y, 0x4),
+            Instruction::Subtract { x, y }                => xy(0x8, x, y, 0x5),
+            Instruction::ShiftRight { x, y }              => xy(0x8, x, y, 0x6),
+            Instruction::SubtractReverse { x, y }         => xy(0x8, x, y, 0x7),
+            Instruction::ShiftLeft { x, y }               => xy(0x8, x, y, 0xE),
+            Instruction::SkipIfRegistersNotEqual { x, y } => xy(0x9, x, y, 0x0),
+            Instruction::SetI { addr }                    => 0xA000 | addr,
+            Instruction::JumpWithOffset { addr }          => 0xB000 | addr,
+            Instruction::Random { x, mask }               => xnn(0xC, x, mask),
+            Instruction::Draw { x, y, rows }              => xy(0xD, x, y, rows as u16),
+            Instruction::SkipIfKeyPressed { x }           => xnn(0xE, x, 0x9E),
+            Instruction::SkipIfKeyNotPressed { x }        => xnn(0xE, x, 0xA1),
+            Instruction::SetILong { .. }                  => 0xF000,
+            Instruction::SelectPlanes { planes }          => xnn(0xF, planes, 0x01),
+            Instruction::GetDelayTimer { x }              => xnn(0xF, x, 0x07),
+            Instruction::AwaitKey { x }                   => xnn(0xF, x, 0x0A),
+            Instruction::SetDelayTimer { x }              => xnn(0xF, x, 0x15),
+            Instruction::SetSoundTimer { x }              => xnn(0xF, x, 0x18),
+            Instruction::AddToI { x }                     => xnn(0xF, x, 0x1E),
+            Instruction::SetIToFont { x }                 => xnn(0xF, x, 0x29),
+            Instruction::SetIToBigFont { x }              => xnn(0xF, x, 0x30),
+            Instruction::StoreBcd { x }                   => xnn(0xF, x, 0x33),
+            Instruction::SetPitch { x }                   => xnn(0xF, x, 0x3A),
+            Instruction::StoreRegisters { x }             => xnn(0xF, x, 0x55),
+            Instruction::LoadRegisters { x }              => xnn(0xF, x, 0x65),
+            Instruction::StoreFlags { x }                 => xnn(0xF, x, 0x75),
+            Instruction::LoadFlags { x }                  => xnn(0xF, x, 0x85),
+        }
+    }
+
+    pub fn encode_operand(&self) -> Option<u16> {
+        match *self {
+            Instruction::SetILong { addr }                => Some(addr),
+            _                                             => None
+        }
+    }
+
+    /**
+     * The earliest variant which supports this instruction
+     */
+    pub fn get_variant(&self) -> Variant {
+        match self {
+            Instruction::ScrollDown { .. } |
+            Instruction::ScrollRight |
+            Instruction::ScrollLeft |
+            Instruction::Exit |
+            Instruction::LowRes |
+            Instruction::HighRes |
+            Instruction::SetIToBigFont { .. } |
+            Instruction::StoreFlags { .. } |
+            Instruction::LoadFlags { .. }                 => Variant::SuperChip,
+            Instruction::ScrollUp { .. } |
+            Instruction::StoreRange { .. } |
+            Instruction::LoadRange { .. } |
+            Instruction::SetILong { .. } |
+            Instruction::SelectPlanes { .. } |
+            Instruction::SetPitch { .. }                  => Variant::XoChip,
+            _ => Variant::Chip8
+        }
+    }
+
+    /**
+     * Size of the instruction in bytes, including any operand words which follow it
+     */
+    pub fn get_size(&self) -> u16 {
+        match self {
+            Instruction::SetILong { .. }                  => 4,
+            _                                             => 2
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::ClearScreen                      => write!(f, "CLS"),
+            Instruction::Return                           => write!(f, "RET"),
+            Instruction::ScrollDown { rows }              => write!(f, "SCD {}", rows),
+            Instruction::ScrollUp { rows }                => write!(f, "SCU {}", rows),
+            Instruction::ScrollRight                      => write!(f, "SCR"),
+            Instruction::ScrollLeft                       => write!(f, "SCL"),
+            Instruction::Exit                             => write!(f, "EXIT"),
+            Instruction::LowRes                           => write!(f, "LOW"),
+            Instruction::HighRes                          => write!(f, "HIGH"),
+            Instruction::Jump { addr }                    => write!(f, "JP 0x{:03X}", addr),
+            Instruction::Call { addr }                    => write!(f, "CALL 0x{:03X}", addr),
+            Instruction::SkipIfEqual { x, value }         => write!(f, "SE V{:X}, 0x{:02X}", x, value),
+            Instruction::SkipIfNotEqual { x, value }      => write!(f, "SNE V{:X}, 0x{:02X}", x, value),
+            Instruction::SkipIfRegistersEqual { x, y }    => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::StoreRange { x, y }              => write!(f, "SAVE V{:X} - V{:X}", x, y),
+            Instruction::LoadRange { x, y }               => write!(f, "LOAD V{:X} - V{:X}", x, y),
+            Instruction::SetRegister { x, value }         => write!(f, "LD V{:X}, 0x{:02X}", x, value),
+            Instruction::AddValue { x, value }            => write!(f, "ADD V{:X}, 0x{:02X}", x, value),
+            Instruction::Copy { x, y }                    => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y }                      => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y }                     => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y }                     => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::Add { x, y }                     => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Subtract { x, y }                => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight { x, y }              => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubtractReverse { x, y }         => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft { x, y }               => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipIfRegistersNotEqual { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::SetI { addr }                    => write!(f, "LD I, 0x{:03X}", addr),
+            Instruction::JumpWithOffset { addr }          => write!(f, "JP V0, 0x{:03X}", addr),
+            Instruction::Random { x, mask }               => write!(f, "RND V{:X}, 0x{:02X}", x, mask),
+            Instruction::Draw { x, y, rows }              => write!(f, "DRW V{:X}, V{:X}, {}", x, y, rows),
+            Instruction::SkipIfKeyPressed { x }           => write!(f, "SKP V{:X}", x),
+            Instruction::SkipIfKeyNotPressed { x }        => write!(f, "SKNP V{:X}", x),
+            Instruction::SetILong { addr }                => write!(f, "LD I, LONG 0x{:04X}", addr),
+            Instruction::SelectPlanes { planes }          => write!(f, "PLANE {}", planes),
+            Instruction::GetDelayTimer { x }              => write!(f, "LD V{:X}, DT", x),
+            Instruction::AwaitKey { x }                   => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelayTimer { x }              => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSoundTimer { x }              => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddToI { x }                     => write!(f, "ADD I, V{:X}", x),
+            Instruction::SetIToFont { x }                 => write!(f, "LD F, V{:X}", x),
+            Instruction::SetIToBigFont { x }              => write!(f, "LD HF, V{:X}", x),
+            Instruction::StoreBcd { x }                   => write!(f, "LD B, V{:X}", x),
+            Instruction::SetPitch { x }                   => write!(f, "PITCH V{:X}", x),
+            Instruction::StoreRegisters { x }             => write!(f, "LD [I], V{:X}", x),
+            Instruction::LoadRegisters { x }              => write!(f, "LD V{:X}, [I]", x),
+            Instruction::StoreFlags { x }                 => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags { x }                  => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
@@ -1,29 +1,57 @@
-#[derive(Debug)]
+use crate::error::EmulatorError;
+#[cfg(feature = "std")]
+use crate::savestate::{StateReader, StateWriter};
+
+#[derive(Debug, Clone)]
 pub struct Keyboard {
     keys: [bool; 16]
 }
 
 impl Keyboard {
-    pub fn new() -> Self {
+    pub const fn new() -> Self {
         Self { keys: [false; 16] }
     }
 
-    pub fn is_pressed(&self, key: u8) -> bool {
-        self.keys[key as usize]
+    /**
+     * Whether a key is held. Programs can ask about any value in a register, but only 0-F are keys
+     */
+    pub fn is_pressed(&self, key: u8) -> Result<bool, EmulatorError> {
+        self.keys.get(key as usize).copied().ok_or(EmulatorError::InvalidKey { key })
+    }
+
+    pub fn press_key(&mut self, key: u8) -> Result<(), EmulatorError> {
+        self.set_key(key, true)
+    }
+
+    pub fn release_key(&mut self, key: u8) -> Result<(), EmulatorError> {
+        self.set_key(key, false)
+    }
+
+    fn set_key(&mut self, key: u8, pressed: bool) -> Result<(), EmulatorError> {
+        let held = self.keys.get_mut(key as usize).ok_or(EmulatorError::InvalidKey { key })?;
+        *held = pressed;
+        Ok(())
     }
 
-    pub fn press_key(&mut self, key: u8) {
-        self.keys[key as usize] = true;
+    #[cfg(feature = "std")]
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        let held = self.keys.iter().enumerate().fold(0u16, |held, (i, key)| held | ((*key as u16) << i));
+        writer.write_u16(held);
     }
 
-    pub fn release_key(&mut self, key: u8) {
-        self.keys[key as usize] = false;
+    #[cfg(feature = "std")]
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), EmulatorError> {
+        let held = reader.read_u16()?;
+        for (i, key) in self.keys.iter_mut().enumerate() {
+            *key = held & (1 << i) != 0;
+        }
+        Ok(())
     }
 
     pub fn get_pressed(&mut self) -> Option<u8> {
         for i in 0..self.keys.len() {
-            if self.is_pressed(i as u8) {
-                self.release_key(i as u8);
+            if self.keys[i] {
+                self.keys[i] = false;
                 return Some(i as u8);
             }
         }
@@ -0,0 +1,121 @@
+// The CHIP-8 keypad, a row at a time - presets list their host keys in this order
+//   1 2 3 C
+//   4 5 6 D
+//   7 8 9 E
+//   A 0 B F
+const KEYPAD_ORDER: [u8; 16] = [0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF];
+
+// The 4x4 block of keys from 1 to V
+const QWERTY: [&[&str]; 16] = [
+    &["1"], &["2"], &["3"], &["4"],
+    &["q"], &["w"], &["e"], &["r"],
+    &["a"], &["s"], &["d"], &["f"],
+    &["z"], &["x"], &["c"], &["v"],
+];
+
+// The same keys as QWERTY by position. The number row types symbols unless shift is held, so both work
+const AZERTY: [&[&str]; 16] = [
+    &["&", "1"], &["é", "2"], &["\"", "3"], &["'", "4"],
+    &["a"], &["z"], &["e"], &["r"],
+    &["q"], &["s"], &["d"], &["f"],
+    &["w"], &["x"], &["c"], &["v"],
+];
+
+// The same keys as QWERTY by position
+const DVORAK: [&[&str]; 16] = [
+    &["1"], &["2"], &["3"], &["4"],
+    &["'"], &[","], &["."], &["p"],
+    &["a"], &["o"], &["e"], &["u"],
+    &[";"], &["q"], &["j"], &["k"],
+];
+
+// Digits on the number pad keys with the same number, which also puts 2, 4, 6 and 8 - the usual
+// directions in CHIP-8 games - in the right places. A to F go on the keys around the edge
+const NUMPAD: [&[&str]; 16] = [
+    &["keypad 1"], &["keypad 2"], &["keypad 3"], &["keypad -"],
+    &["keypad 4"], &["keypad 5"], &["keypad 6"], &["keypad +"],
+    &["keypad 7"], &["keypad 8"], &["keypad 9"], &["keypad enter"],
+    &["keypad /"], &["keypad 0"], &["keypad *"], &["keypad ."],
+];
+
+// The keys printed on the COSMAC VIP's keypad, so each key is typed as its hex digit
+const VIP: [&[&str]; 16] = [
+    &["1"], &["2"], &["3"], &["c"],
+    &["4"], &["5"], &["6"], &["d"],
+    &["7"], &["8"], &["9"], &["e"],
+    &["a"], &["0"], &["b"], &["f"],
+];
+
+/**
+ * Which host keys press each CHIP-8 key. Host keys are named the way SDL names them, in any case -
+ * a character for letter, number and symbol keys, or names like `space`, `up` and `keypad 5`.
+ * A CHIP-8 key can have any number of host keys
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyMap {
+    bindings: Vec<(String, u8)>, // Lowercase host key name, and the CHIP-8 key it presses
+}
+
+impl KeyMap {
+    /**
+     * Built in presets - qwerty, azerty, dvorak, numpad and vip
+     */
+    pub fn from_name(name: &str) -> Option<KeyMap> {
+        match name.to_lowercase().as_str() {
+            "qwerty" => Some(KeyMap::from_layout(&QWERTY)),
+            "azerty" => Some(KeyMap::from_layout(&AZERTY)),
+            "dvorak" => Some(KeyMap::from_layout(&DVORAK)),
+            "numpad" => Some(KeyMap::from_layout(&NUMPAD)),
+            "vip"    => Some(KeyMap::from_layout(&VIP)),
+            _        => None
+        }
+    }
+
+    fn from_layout(layout: &[&[&str]; 16]) -> KeyMap {
+        let mut key_map = KeyMap { bindings: Vec::new() };
+        for (key, host_keys) in KEYPAD_ORDER.iter().zip(layout.iter()) {
+            key_map.bind(*key, host_keys);
+        }
+        key_map
+    }
+
+    /**
+     * Replace the host keys for a CHIP-8 key. A host key can only press one CHIP-8 key, so it's
+     * taken away from any other key it was bound to
+     */
+    pub fn bind(&mut self, key: u8, host_keys: &[&str]) {
+        let host_keys: Vec<String> = host_keys.iter().map(|host_key| host_key.trim().to_lowercase()).collect();
+        self.bindings.retain(|(host_key, bound_key)| *bound_key != key && !host_keys.contains(host_key));
+        self.bindings.extend(host_keys.into_iter().map(|host_key| (host_key, key)));
+    }
+
+    /**
+     * Replace the host keys for a CHIP-8 key from a comma separated list, e.g. `w, up`
+     */
+    pub fn bind_list(&mut self, key: u8, host_keys: &str) {
+        // A comma on its own is a host key rather than a separator
+        let host_keys: Vec<&str> = match host_keys.trim() {
+            "," => vec![","],
+            list => list.split(',').map(|host_key| host_key.trim()).filter(|host_key| !host_key.is_empty()).collect()
+        };
+        self.bind(key, &host_keys);
+    }
+
+    pub fn get_bindings(&self) -> &[(String, u8)] {
+        &self.bindings
+    }
+
+    /**
+     * The CHIP-8 key a host key presses, if any
+     */
+    pub fn get_key(&self, host_key: &str) -> Option<u8> {
+        let host_key = host_key.to_lowercase();
+        self.bindings.iter().find(|(bound, _)| *bound == host_key).map(|(_, key)| *key)
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        KeyMap::from_layout(&QWERTY)
+    }
+}
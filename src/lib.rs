@@ -0,0 +1,96 @@
+//! A CHIP-8, SUPER-CHIP and XO-CHIP emulator.
+//!
+//! `Chip8` is the whole machine. Load a program into it, then either drive it a frame at a time
+//! with `run_frame`, an instruction at a time with `step`, or hand it a frontend with `run`:
+//!
+//! ```no_run
+//! # #[cfg(feature = "std")] {
+//! use rusty_chip::{Chip8, Quirks, Variant};
+//!
+//! let mut chip8 = Chip8::new(Variant::Chip8, Quirks::for_variant(Variant::Chip8), 1234);
+//! chip8.load_rom_bytes(&[0x00, 0xE0, 0x12, 0x02]).unwrap();
+//! chip8.set_key(0x5, true).unwrap();
+//! chip8.run_frame(&[]).unwrap();
+//! let pixels = chip8.get_framebuffer();
+//! # }
+//! ```
+//!
+//! The SDL frontend is only built with the `sdl` feature, and the terminal frontend with the `tui`
+//! feature. Both are on by default.
+//!
+//! Without the `std` feature only the core machine is built - `Cpu`, `Memory`, `Display`,
+//! `Keyboard` and the instruction decoder - which needs neither the standard library nor an
+//! allocator, so it can run on microcontrollers. Programs are loaded from byte slices with
+//! `Cpu::load_program`, and random numbers come from any `RandomSource`.
+//!
+//! Memory is sized for XO-CHIP's 64 KiB unless told otherwise - a `Cpu<R, 4096>` only stores the
+//! 4 KiB CHIP-8 and SUPER-CHIP can address. `Cpu::new` and everything passed to it are const fns,
+//! so the machine can be built straight into a `static` instead of on a small stack.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "std")]
+pub mod assembler;
+#[cfg(feature = "std")]
+pub mod audio;
+#[cfg(feature = "std")]
+pub mod chip8;
+pub mod cpu;
+#[cfg(feature = "std")]
+pub mod debugger;
+#[cfg(feature = "std")]
+pub mod disassembler;
+pub mod display;
+pub mod error;
+#[cfg(feature = "std")]
+pub mod frontend;
+#[cfg(feature = "std")]
+pub mod gdb;
+#[cfg(feature = "std")]
+pub mod headless;
+pub mod instruction;
+pub mod keyboard;
+#[cfg(feature = "std")]
+pub mod keymap;
+pub mod memory;
+#[cfg(feature = "std")]
+pub mod movie;
+pub mod palette;
+pub mod quirks;
+#[cfg(feature = "std")]
+pub mod rewind;
+pub mod rng;
+#[cfg(feature = "std")]
+pub mod rom;
+#[cfg(feature = "std")]
+pub mod savestate;
+#[cfg(feature = "std")]
+pub mod screenshot;
+#[cfg(feature = "sdl")]
+pub mod sdl;
+pub mod timing;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod util;
+pub mod variant;
+#[cfg(feature = "std")]
+pub mod video;
+
+#[cfg(feature = "std")]
+pub use chip8::{Chip8, StopCondition, FRAMES_PER_SECOND};
+pub use cpu::Cpu;
+pub use display::Display;
+pub use error::EmulatorError;
+#[cfg(feature = "std")]
+pub use frontend::{AudioSink, Clock, InputEvent, InputSource, VideoSink};
+pub use keyboard::Keyboard;
+#[cfg(feature = "std")]
+pub use keymap::KeyMap;
+pub use memory::Memory;
+pub use palette::Palette;
+pub use quirks::{LoadStore, Quirks};
+pub use rng::{RandomSource, Rng};
+#[cfg(feature = "std")]
+pub use rom::Rom;
+pub use timing::TimingMode;
+pub use variant::Variant;
@@ -1,42 +1,191 @@
-use std::env;
-use rand::Rng;
+use std::{env, process};
 
-use chip8::Chip8;
-use util::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use rusty_chip::chip8::{Chip8, FRAMES_PER_SECOND};
+use rusty_chip::disassembler::disassemble;
+use rusty_chip::headless::{run_headless, HeadlessOptions, HeadlessOutcome};
+use rusty_chip::audio::AudioSettings;
+use rusty_chip::keymap::KeyMap;
+use rusty_chip::movie::Movie;
+use rusty_chip::palette::Palette;
+use rusty_chip::rng::Rng;
+use rusty_chip::rom::Rom;
+use rusty_chip::video::VideoFormat;
+#[cfg(any(feature = "sdl", feature = "tui"))]
+use rusty_chip::frontend::WallClock;
+#[cfg(feature = "sdl")]
+use rusty_chip::sdl::SdlFrontend;
+#[cfg(feature = "tui")]
+use rusty_chip::tui::TuiFrontend;
 
-pub mod chip8;
-pub mod cpu;
-pub mod display;
-pub mod keyboard;
-pub mod memory;
-pub mod rom;
-pub mod util;
+use cli::{Command, Options};
+
+mod cli;
+mod config;
+
+// One minute of emulated time
+const DEFAULT_HEADLESS_FRAMES: u64 = 60 * FRAMES_PER_SECOND as u64;
 
 fn main() {
     // Setup emulator
     let args: Vec<String> = env::args().collect();
-    let rom_file = &args[1];
-
-    let mut chip8 = Chip8::new();
-    chip8.load_rom(&rom_file);
-
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem
-        .window("Rusty Chip", DISPLAY_WIDTH as u32 * 10, DISPLAY_HEIGHT as u32 * 10)
-        .position_centered()
-        .opengl()
-        .build()
-        .map_err(|e| e.to_string())
-        .unwrap();
-
-    let mut canvas = window
-        .into_canvas()
-        .present_vsync()
-        .build()
-        .map_err(|e| e.to_string())
-        .unwrap();
-
-    canvas.set_scale(10f32, 10f32).unwrap();
-    chip8.run(&sdl_context, &mut canvas);
+    let options = Options::parse(&args[1..]);
+
+    if options.command == Command::Disassemble {
+        let rom = Rom::load(&options.rom_file).unwrap_or_else(|e| {
+            eprintln!("Failed to load ROM: {}", e);
+            process::exit(1);
+        });
+        for line in disassemble(&rom, options.variant) {
+            println!("{}", line);
+        }
+        return;
+    }
+
+    // A movie has to be played back with the settings it was recorded with
+    let movie = options.play_file.as_ref().map(|file| Movie::load(file).unwrap_or_else(|e| {
+        eprintln!("Failed to load movie: {}", e);
+        process::exit(1);
+    }));
+    let (variant, quirks, seed, instructions_per_second, timing) = match &movie {
+        Some(movie) => (movie.variant, movie.quirks, movie.seed, movie.instructions_per_second, movie.timing),
+        None        => (options.variant, options.quirks, options.seed.unwrap_or_else(Rng::random_seed), options.instructions_per_second, options.timing)
+    };
+
+    // Print the seed so that a run can be reproduced with --seed
+    eprintln!("Random seed: {}", seed);
+
+    let mut chip8 = Chip8::new(variant, quirks, seed);
+    chip8.set_instructions_per_second(instructions_per_second);
+    chip8.set_timing(timing);
+    chip8.set_rewind_length(options.rewind_seconds * FRAMES_PER_SECOND as usize);
+    chip8.set_muted(options.mute);
+    if let Err(e) = chip8.load_rom(&options.rom_file) {
+        eprintln!("Failed to load ROM: {}", e);
+        process::exit(1);
+    }
+
+    if let Some(movie) = movie {
+        if let Err(e) = chip8.start_playback(movie, options.loop_playback) {
+            eprintln!("Failed to play movie: {}", e);
+            process::exit(1);
+        }
+    } else if let Some(file) = &options.record_file {
+        chip8.start_recording(file);
+    }
+
+    if let Some(file) = &options.video_file {
+        let format = VideoFormat::from_file(file).unwrap_or(VideoFormat::Gif);
+        if let Err(e) = chip8.start_video(file, format, options.scale, options.palette) {
+            eprintln!("Failed to record video to {}: {}", file, e);
+            process::exit(1);
+        }
+    }
+
+    if options.debug {
+        chip8.enable_debugger();
+    }
+
+    if let Some(port) = options.gdb_port {
+        if let Err(e) = chip8.attach_gdb(port) {
+            eprintln!("Failed to start GDB server: {}", e);
+            process::exit(1);
+        }
+    }
+
+    if options.headless {
+        if let Some(condition) = options.stop_condition {
+            chip8.set_stop_condition(condition);
+        }
+
+        // Without a stop condition, run for the requested number of frames and count that as success
+        let has_condition = options.stop_condition.is_some() || options.stable_frames.is_some();
+        let headless_options = HeadlessOptions {
+            max_frames: options.frames.unwrap_or(DEFAULT_HEADLESS_FRAMES),
+            stable_frames: options.stable_frames,
+            dump_format: options.dump_format,
+            dump_file: options.dump_file.clone(),
+            screenshot_file: options.screenshot_file.clone(),
+            screenshot_scale: options.scale,
+            palette: options.palette
+        };
+
+        let status = match run_headless(&mut chip8, &headless_options) {
+            Ok(HeadlessOutcome::FrameLimit) if has_condition => {
+                eprintln!("Stop condition not met after {} frames", headless_options.max_frames);
+                2
+            },
+            Ok(_) => 0,
+            Err(e) => {
+                eprintln!("Emulation stopped: {}", e);
+                1
+            }
+        };
+        process::exit(status);
+    }
+
+    if options.tui {
+        run_tui(&mut chip8, options.palette, options.key_map);
+        return;
+    }
+
+    let audio_settings = AudioSettings {
+        frequency: options.tone_frequency,
+        volume: options.volume,
+        waveform: options.waveform
+    };
+    run_sdl(&mut chip8, audio_settings, options.palette, &options.key_map);
+}
+
+#[cfg(feature = "sdl")]
+fn run_sdl(chip8: &mut Chip8, audio_settings: AudioSettings, palette: Palette, key_map: &KeyMap) {
+    let mut frontend = SdlFrontend::new(audio_settings, palette, key_map).unwrap();
+
+    let mut clock = WallClock::new(FRAMES_PER_SECOND);
+    let result = chip8.run(&mut frontend.video, &mut frontend.input, &mut frontend.audio, &mut clock);
+    print_messages(chip8);
+    if let Err(e) = result {
+        eprintln!("Emulation stopped: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run_sdl(_chip8: &mut Chip8, _audio_settings: AudioSettings, _palette: Palette, _key_map: &KeyMap) {
+    eprintln!("Built without SDL support - use --tui or --headless instead");
+    process::exit(1);
+}
+
+#[cfg(feature = "tui")]
+fn run_tui(chip8: &mut Chip8, palette: Palette, key_map: KeyMap) {
+    let mut frontend = TuiFrontend::new(palette, key_map).unwrap_or_else(|e| {
+        eprintln!("Failed to set up the terminal: {}", e);
+        process::exit(1);
+    });
+
+    let mut clock = WallClock::new(FRAMES_PER_SECOND);
+    let result = chip8.run(&mut frontend.video, &mut frontend.input, &mut frontend.audio, &mut clock);
+
+    // Put the terminal back to normal before reporting anything
+    drop(frontend);
+    print_messages(chip8);
+    if let Err(e) = result {
+        eprintln!("Emulation stopped: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "tui"))]
+fn run_tui(_chip8: &mut Chip8, _palette: Palette, _key_map: KeyMap) {
+    eprintln!("Built without terminal support");
+    process::exit(1);
+}
+
+/**
+ * Print status messages left over once the frontend has stopped, e.g. from saving a video
+ */
+#[cfg(any(feature = "sdl", feature = "tui"))]
+fn print_messages(chip8: &mut Chip8) {
+    for message in chip8.take_messages() {
+        eprintln!("{}", message);
+    }
 }
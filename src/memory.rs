@@ -1,35 +1,176 @@
-use crate::{rom::Rom, util::FONTS};
+use core::cell::Cell;
 
-#[derive(Debug)]
-pub struct Memory {
-    data: [u8; 0x1000], // 4096 memory locations (i.e. 0x1000)
+use crate::{error::EmulatorError, util::{BIG_FONTS, BIG_FONT_START, FONTS, MAX_MEMORY_SIZE, MAX_WATCHPOINTS}, variant::Variant};
+#[cfg(feature = "std")]
+use crate::savestate::{StateReader, StateWriter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+}
+
+/**
+ * A watched memory access made by the running program
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub addr: u16,
+    pub kind: WatchKind,
+}
+
+/**
+ * SIZE is how much memory is actually stored. The default is enough for any variant, but a
+ * `Memory<4096>` is all CHIP-8 and SUPER-CHIP need, which matters on small microcontrollers
+ */
+#[derive(Debug, Clone)]
+pub struct Memory<const SIZE: usize = MAX_MEMORY_SIZE> {
+    data: [u8; SIZE], // Only the first `size` locations are addressable
+    size: usize, // 4096 memory locations (i.e. 0x1000) for CHIP-8 and SUPER-CHIP, 65536 for XO-CHIP
+    watchpoints: [Option<(u16, WatchKind)>; MAX_WATCHPOINTS],
+    watch_hit: Cell<Option<WatchHit>>, // Reads only take &self, so the last hit has to be recorded through a Cell
 }
 
-impl Memory {
-    pub fn new() -> Self {
-        let mut data: [u8; 0x1000] = [0; 0x1000];
+impl<const SIZE: usize> Memory<SIZE> {
+    /**
+     * This is a const fn so that memory can be built straight into a static, rather than on a
+     * stack which may be too small for it. If SIZE is smaller than the variant's memory, anything
+     * past SIZE is out of bounds
+     */
+    pub const fn new(variant: Variant) -> Self {
+        let mut data: [u8; SIZE] = [0; SIZE];
 
         // Load Font data starting at memory 0 - there is no actual specification for where font data should be...
         // So let's just put it somewhere safe.
-        for i in 0..FONTS.len() {
+        let mut i = 0;
+        while i < FONTS.len() {
             data[i] = FONTS[i];
+            i += 1;
+        }
+
+        let mut i = 0;
+        while i < BIG_FONTS.len() {
+            data[BIG_FONT_START as usize + i] = BIG_FONTS[i];
+            i += 1;
+        }
+
+        let size = match variant.get_memory_size() < SIZE {
+            true  => variant.get_memory_size(),
+            false => SIZE
+        };
+
+        Self {
+            data,
+            size,
+            watchpoints: [None; MAX_WATCHPOINTS],
+            watch_hit: Cell::new(None)
+        }
+    }
+
+    pub fn get_size(&self) -> usize {
+        self.size
+    }
+
+    pub fn read(&self, addr: u16) -> Result<u8, EmulatorError> {
+        let value = self.peek(addr)?;
+        self.check_watchpoint(addr, WatchKind::Read);
+        Ok(value)
+    }
+
+    /**
+     * Read memory without triggering watchpoints - used for instruction fetches and by debuggers
+     */
+    pub fn peek(&self, addr: u16) -> Result<u8, EmulatorError> {
+        Ok(self.data[self.check_addr(addr as usize)?])
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) -> Result<(), EmulatorError> {
+        self.data[self.check_addr(addr as usize)?] = data;
+        self.check_watchpoint(addr, WatchKind::Write);
+        Ok(())
+    }
+
+    /**
+     * Write memory without triggering watchpoints - used by debuggers
+     */
+    pub fn poke(&mut self, addr: u16, data: u8) -> Result<(), EmulatorError> {
+        self.data[self.check_addr(addr as usize)?] = data;
+        Ok(())
+    }
+
+    /**
+     * Watch for the program accessing addr. Returns false if there is no room for another watchpoint
+     */
+    pub fn add_watchpoint(&mut self, addr: u16, kind: WatchKind) -> bool {
+        if self.watchpoints.contains(&Some((addr, kind))) {
+            return true;
+        }
+
+        match self.watchpoints.iter_mut().find(|watchpoint| watchpoint.is_none()) {
+            Some(slot) => {
+                *slot = Some((addr, kind));
+                true
+            },
+            None => false
         }
+    }
 
-        Self { data }
+    pub fn remove_watchpoint(&mut self, addr: u16) {
+        for watchpoint in self.watchpoints.iter_mut() {
+            if matches!(watchpoint, Some((watched, _)) if *watched == addr) {
+                *watchpoint = None;
+            }
+        }
     }
 
-    pub fn read(&self, addr: u16) -> u8 {
-        self.data[addr as usize]
+    pub fn get_watchpoints(&self) -> impl Iterator<Item = &(u16, WatchKind)> {
+        self.watchpoints.iter().flatten()
     }
 
-    pub fn write(&mut self, addr: u16, data: u8) {
-        self.data[addr as usize] = data;
+    /**
+     * Return the most recent watched access since the last call, if there was one
+     */
+    pub fn take_watch_hit(&self) -> Option<WatchHit> {
+        self.watch_hit.take()
     }
 
-    pub fn load_program(&mut self, rom: Rom) {
+    pub fn load_program(&mut self, program: &[u8]) -> Result<(), EmulatorError> {
         // Programs load into memory at address 0x200
-        for i in 0..rom.get_size() {
-            self.data[0x200 + i] = rom.get_byte(i);
+        for (i, byte) in program.iter().enumerate() {
+            self.data[self.check_addr(0x200 + i)?] = *byte;
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Watchpoints belong to whoever is debugging, so aren't part of the saved state
+     */
+    #[cfg(feature = "std")]
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u32(self.size as u32);
+        writer.write_bytes(&self.data[..self.size]);
+    }
+
+    #[cfg(feature = "std")]
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), EmulatorError> {
+        if reader.read_u32()? as usize != self.size {
+            return Err(EmulatorError::InvalidSaveState { reason: "memory size does not match" });
+        }
+        self.data[..self.size].copy_from_slice(reader.read_bytes(self.size)?);
+        Ok(())
+    }
+
+    fn check_watchpoint(&self, addr: u16, kind: WatchKind) {
+        if self.watchpoints.contains(&Some((addr, kind))) {
+            self.watch_hit.set(Some(WatchHit { addr, kind }));
         }
     }
-}
\ No newline at end of file
+
+    fn check_addr(&self, addr: usize) -> Result<usize, EmulatorError> {
+        match addr < self.size {
+            true  => Ok(addr),
+            false => Err(EmulatorError::MemoryOutOfBounds { addr: addr as u16 })
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use std::{fmt, fs};
+use std::io;
+
+use crate::{quirks::Quirks, timing::TimingMode, variant::Variant};
+
+/*
+ * Movie file format
+ *
+ * Movies are plain text, one item per line:
+ *
+ *   rusty-chip movie 1
+ *   rom 0123456789abcdef     FNV-1a hash of the ROM the movie was recorded with
+ *   variant schip
+ *   quirks 1c                Quirks bits (see Quirks::to_bits), in hex
+ *   seed 12345               Random number generator seed
+ *   ips 600                  Instructions per second (optional, defaults to 600)
+ *   timing fixed             Timing model, fixed or vip (optional, defaults to fixed)
+ *   120 press 5              Key 5 pressed at the start of frame 120
+ *   135 release 5
+ *   end 600                  Number of frames recorded
+ *
+ * Key events are listed in the order they happened. Playback is only exact if the ROM, variant,
+ * quirks, seed, speed and timing all match the recording.
+ */
+
+const MOVIE_HEADER: &str = "rusty-chip movie 1";
+
+// Speed assumed for movies recorded before the speed was configurable
+const DEFAULT_MOVIE_IPS: u32 = 600;
+
+/**
+ * Problems reading a movie, or playing it back
+ */
+#[derive(Debug)]
+pub enum MovieError {
+    Io(io::Error),
+    Invalid { line: usize, reason: &'static str },
+    Mismatch { reason: &'static str }, // The movie was recorded with a different ROM or settings
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::Io(e)                    => write!(f, "{}", e),
+            MovieError::Invalid { line, reason } => write!(f, "Invalid movie at line {} - {}", line, reason),
+            MovieError::Mismatch { reason }      => write!(f, "Movie can't be played back - {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<io::Error> for MovieError {
+    fn from(e: io::Error) -> Self {
+        MovieError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovieEvent {
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub variant: Variant,
+    pub quirks: Quirks,
+    pub seed: u64,
+    pub instructions_per_second: u32,
+    pub timing: TimingMode,
+    pub events: Vec<MovieEvent>,
+    pub length: u64, // Number of frames
+}
+
+impl Movie {
+    pub fn new(rom_hash: u64, variant: Variant, quirks: Quirks, seed: u64, instructions_per_second: u32, timing: TimingMode) -> Self {
+        Self { rom_hash, variant, quirks, seed, instructions_per_second, timing, events: Vec::new(), length: 0 }
+    }
+
+    pub fn load(file: &str) -> Result<Movie, MovieError> {
+        let contents = fs::read_to_string(file)?;
+        Movie::parse(&contents)
+    }
+
+    pub fn save(&self, file: &str) -> io::Result<()> {
+        fs::write(file, self.to_string())
+    }
+
+    pub fn parse(contents: &str) -> Result<Movie, MovieError> {
+        let mut lines = contents.lines().enumerate().map(|(i, line)| (i + 1, line.trim())).filter(|(_, line)| !line.is_empty());
+
+        match lines.next() {
+            Some((_, MOVIE_HEADER)) => (),
+            _ => return Err(MovieError::Invalid { line: 1, reason: "not a rusty-chip movie" })
+        }
+
+        let mut rom_hash = None;
+        let mut variant = None;
+        let mut quirks = None;
+        let mut seed = None;
+        let mut instructions_per_second = DEFAULT_MOVIE_IPS;
+        let mut timing = TimingMode::Fixed;
+        let mut events: Vec<MovieEvent> = Vec::new();
+        let mut length = None;
+
+        for (line_number, line) in lines {
+            let invalid = |reason| MovieError::Invalid { line: line_number, reason };
+            let parts: Vec<&str> = line.split_whitespace().collect();
+
+            match parts.as_slice() {
+                ["rom", hash]     => rom_hash = Some(u64::from_str_radix(hash, 16).map_err(|_| invalid("invalid ROM hash"))?),
+                ["variant", name] => variant = Some(Variant::from_name(name).ok_or_else(|| invalid("unknown variant"))?),
+                ["quirks", bits]  => quirks = Some(Quirks::from_bits(u8::from_str_radix(bits, 16).map_err(|_| invalid("invalid quirks"))?)),
+                ["seed", value]   => seed = Some(value.parse().map_err(|_| invalid("invalid seed"))?),
+                ["ips", value]    => instructions_per_second = value.parse().map_err(|_| invalid("invalid instructions per second"))?,
+                ["timing", name]  => timing = TimingMode::from_name(name).ok_or_else(|| invalid("unknown timing"))?,
+                ["end", frame]    => length = Some(frame.parse().map_err(|_| invalid("invalid frame number"))?),
+                [frame, action @ ("press" | "release"), key] => {
+                    let frame: u64 = frame.parse().map_err(|_| invalid("invalid frame number"))?;
+                    let key = u8::from_str_radix(key, 16).ok().filter(|key| *key < 16).ok_or_else(|| invalid("invalid key"))?;
+                    if events.last().map(|last| last.frame > frame).unwrap_or(false) {
+                        return Err(invalid("events are out of order"));
+                    }
+                    events.push(MovieEvent { frame, key, pressed: *action == "press" });
+                },
+                _                 => return Err(invalid("unrecognised line"))
+            }
+        }
+
+        // Missing fields are reported at the end of the file
+        let missing = |reason| MovieError::Invalid { line: contents.lines().count(), reason };
+        Ok(Movie {
+            rom_hash: rom_hash.ok_or_else(|| missing("missing ROM hash"))?,
+            variant: variant.ok_or_else(|| missing("missing variant"))?,
+            quirks: quirks.ok_or_else(|| missing("missing quirks"))?,
+            seed: seed.ok_or_else(|| missing("missing seed"))?,
+            instructions_per_second,
+            timing,
+            length: length.ok_or_else(|| missing("missing end frame"))?,
+            events
+        })
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", MOVIE_HEADER)?;
+        writeln!(f, "rom {:016x}", self.rom_hash)?;
+        writeln!(f, "variant {}", self.variant.get_name())?;
+        writeln!(f, "quirks {:02x}", self.quirks.to_bits())?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "ips {}", self.instructions_per_second)?;
+        writeln!(f, "timing {}", self.timing.get_name())?;
+        for event in self.events.iter() {
+            let action = match event.pressed {
+                true  => "press",
+                false => "release"
+            };
+            writeln!(f, "{} {} {:X}", event.frame, action, event.key)?;
+        }
+        writeln!(f, "end {}", self.length)
+    }
+}
+
+/**
+ * Collects key events into a movie, which is written out when recording finishes
+ */
+#[derive(Debug)]
+pub struct MovieRecorder {
+    movie: Movie,
+    file: String,
+}
+
+impl MovieRecorder {
+    pub fn new(movie: Movie, file: &str) -> Self {
+        Self { movie, file: file.to_string() }
+    }
+
+    pub fn get_file(&self) -> &str {
+        &self.file
+    }
+
+    pub fn record(&mut self, frame: u64, key: u8, pressed: bool) {
+        self.movie.events.push(MovieEvent { frame, key, pressed });
+    }
+
+    /**
+     * Write the movie, ending it at the given frame
+     */
+    pub fn finish(mut self, length: u64) -> io::Result<()> {
+        self.movie.length = length;
+        self.movie.save(&self.file)
+    }
+}
+
+/**
+ * Steps through a movie's key events as frames are played back
+ */
+#[derive(Debug)]
+pub struct MoviePlayer {
+    movie: Movie,
+    next_event: usize,
+}
+
+impl MoviePlayer {
+    pub fn new(movie: Movie) -> Self {
+        Self { movie, next_event: 0 }
+    }
+
+    pub fn get_movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    /**
+     * Return the events which happen at the start of the given frame
+     */
+    pub fn take_events(&mut self, frame: u64) -> &[MovieEvent] {
+        let start = self.next_event;
+        while self.next_event < self.movie.events.len() && self.movie.events[self.next_event].frame <= frame {
+            self.next_event += 1;
+        }
+        &self.movie.events[start..self.next_event]
+    }
+
+    pub fn is_finished(&self, frame: u64) -> bool {
+        frame >= self.movie.length
+    }
+
+    pub fn restart(&mut self) {
+        self.next_event = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chip8::Chip8, frontend::InputEvent};
+
+    // Waits for a key, then draws a random sprite and waits again
+    const PROGRAM: [u8; 10] = [0xF1, 0x0A, 0xC2, 0xFF, 0xA2, 0x00, 0xD2, 0x25, 0x12, 0x00];
+
+    fn get_chip8() -> Chip8 {
+        let mut chip8 = Chip8::new(Variant::Chip8, Quirks::for_variant(Variant::Chip8), 7);
+        chip8.load_rom_bytes(&PROGRAM).unwrap();
+        chip8
+    }
+
+    fn get_events(frame: u64) -> Vec<InputEvent> {
+        match frame % 4 {
+            1 => vec![InputEvent::KeyDown(0x5)],
+            3 => vec![InputEvent::KeyUp(0x5)],
+            _ => Vec::new()
+        }
+    }
+
+    #[test]
+    fn parses_what_it_writes() {
+        let mut movie = Movie::new(0x0123456789ABCDEF, Variant::SuperChip, Quirks::chip48(), 42, 1000, TimingMode::CosmacVip);
+        movie.events.push(MovieEvent { frame: 3, key: 0xA, pressed: true });
+        movie.events.push(MovieEvent { frame: 9, key: 0xA, pressed: false });
+        movie.length = 20;
+
+        let parsed = Movie::parse(&movie.to_string()).unwrap();
+        assert_eq!(parsed.to_string(), movie.to_string());
+        assert_eq!(parsed.quirks, Quirks::chip48());
+        assert_eq!(parsed.events, movie.events);
+    }
+
+    #[test]
+    fn rejects_invalid_movies() {
+        assert!(matches!(Movie::parse("not a movie"), Err(MovieError::Invalid { line: 1, .. })));
+
+        let out_of_order = format!("{}\nrom 0\nvariant chip8\nquirks 0\nseed 0\n5 press 1\n2 press 2\nend 10", MOVIE_HEADER);
+        assert!(matches!(Movie::parse(&out_of_order), Err(MovieError::Invalid { line: 7, .. })));
+
+        let missing_end = format!("{}\nrom 0\nvariant chip8\nquirks 0\nseed 0", MOVIE_HEADER);
+        assert!(matches!(Movie::parse(&missing_end), Err(MovieError::Invalid { .. })));
+    }
+
+    #[test]
+    fn plays_back_what_was_recorded() {
+        let file = std::env::temp_dir().join(format!("rusty-chip-test-{}.movie", std::process::id()));
+        let file = file.to_str().unwrap();
+
+        let mut recording = get_chip8();
+        recording.start_recording(file);
+        for frame in 0..20 {
+            recording.run_frame(&get_events(frame)).unwrap();
+        }
+        recording.finish_recording();
+
+        let movie = Movie::load(file).unwrap();
+        fs::remove_file(file).unwrap();
+        assert_eq!(movie.length, 20);
+        assert_eq!(movie.events.len(), 10);
+
+        // Keyboard input is ignored while the movie plays
+        let mut playback = get_chip8();
+        playback.start_playback(movie, false).unwrap();
+        for _ in 0..20 {
+            playback.run_frame(&[InputEvent::KeyDown(0x5)]).unwrap();
+        }
+        assert_eq!(playback.save_state(), recording.save_state());
+    }
+
+    #[test]
+    fn refuses_to_play_with_different_settings() {
+        let movie = Movie::new(0, Variant::Chip8, Quirks::for_variant(Variant::Chip8), 7, 600, TimingMode::Fixed);
+        assert!(matches!(get_chip8().start_playback(movie, false), Err(MovieError::Mismatch { .. })));
+    }
+}
@@ -0,0 +1,87 @@
+/**
+ * Colour for each combination of bitplanes, as (red, green, blue). Only the first two are used
+ * outside of XO-CHIP
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [(u8, u8, u8); 4],
+}
+
+impl Palette {
+    pub fn new(colors: [(u8, u8, u8); 4]) -> Self {
+        Self { colors }
+    }
+
+    pub fn get_color(&self, pixel: u8) -> (u8, u8, u8) {
+        self.colors[pixel as usize & 0x3]
+    }
+
+    /**
+     * Built in themes - classic, amber, green, lcd and high-contrast
+     */
+    #[cfg(feature = "std")]
+    pub fn from_name(name: &str) -> Option<Palette> {
+        match name.to_lowercase().as_str() {
+            "classic" | "default" => Some(Palette::default()),
+            // Amber monochrome monitor
+            "amber"               => Some(Palette::new([(26, 14, 0), (255, 176, 0), (191, 120, 0), (102, 60, 0)])),
+            // P1 green phosphor monitor
+            "green"               => Some(Palette::new([(0, 20, 0), (51, 255, 51), (0, 170, 0), (0, 94, 0)])),
+            // Dark pixels on a greenish LCD, like early handhelds
+            "lcd"                 => Some(Palette::new([(155, 188, 15), (15, 56, 15), (48, 98, 48), (139, 172, 15)])),
+            // Colours which are easy to tell apart, for XO-CHIP games
+            "high-contrast"       => Some(Palette::new([(0, 0, 0), (255, 255, 255), (255, 255, 0), (0, 170, 255)])),
+            _                     => None
+        }
+    }
+
+    /**
+     * Parse four comma separated colours, e.g. `#000000,#ffffff,#aaaaaa,#555555`
+     */
+    pub fn parse(value: &str) -> Option<Palette> {
+        let mut colors = [(0, 0, 0); 4];
+        let mut parts = value.split(',');
+        for color in colors.iter_mut() {
+            *color = parse_color(parts.next()?)?;
+        }
+        match parts.next() {
+            Some(_) => None,
+            None    => Some(Palette::new(colors))
+        }
+    }
+
+    pub fn set_background(&mut self, color: (u8, u8, u8)) {
+        self.colors[0] = color;
+    }
+
+    pub fn set_foreground(&mut self, color: (u8, u8, u8)) {
+        self.colors[1] = color;
+    }
+}
+
+/**
+ * Parse a colour written as hex, e.g. `#ffb000` or `ffb000`
+ */
+pub fn parse_color(value: &str) -> Option<(u8, u8, u8)> {
+    let digits = value.trim();
+    let digits = digits.strip_prefix('#').unwrap_or(digits);
+    if digits.len() != 6 || !digits.is_ascii() {
+        return None;
+    }
+    let component = |index: usize| u8::from_str_radix(&digits[index..index + 2], 16).ok();
+    Some((component(0)?, component(2)?, component(4)?))
+}
+
+impl Default for Palette {
+    /**
+     * White on black, with greys for XO-CHIP's extra colours
+     */
+    fn default() -> Self {
+        Self::new([
+            (0, 0, 0),
+            (255, 255, 255),
+            (170, 170, 170),
+            (85, 85, 85),
+        ])
+    }
+}
@@ -0,0 +1,93 @@
+use crate::variant::Variant;
+
+/**
+ * Where FX55/FX65 leave the I register after storing or loading V0 to VX
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadStore {
+    Increment,    // I + X + 1, as on the COSMAC VIP
+    IncrementByX, // I + X, as on CHIP-48
+    Unchanged,    // I is left alone, as on SUPER-CHIP 1.1
+}
+
+/**
+ * Behaviours which differ between CHIP-8 interpreters. Each flag describes the alternative to the
+ * original COSMAC VIP interpretation of the instruction (apart from those the VIP itself had)
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    pub shift: bool,        // 8XY6/8XYE shift VX in place and ignore VY
+    pub load_store: LoadStore,
+    pub jump: bool,         // BNNN jumps to XNN + VX instead of NNN + V0
+    pub vf_reset: bool,     // 8XY1/8XY2/8XY3 reset VF to 0
+    pub clipping: bool,     // DXYN clips sprites at the edge of the screen instead of wrapping them around
+    pub display_wait: bool, // DXYN waits for the vertical blank, so at most one sprite is drawn per frame
+}
+
+impl Quirks {
+    pub const fn cosmac_vip() -> Self {
+        Self { shift: false, load_store: LoadStore::Increment, jump: false, vf_reset: true, clipping: true, display_wait: true }
+    }
+
+    pub const fn chip48() -> Self {
+        Self { shift: true, load_store: LoadStore::IncrementByX, jump: true, vf_reset: false, clipping: true, display_wait: false }
+    }
+
+    pub const fn super_chip() -> Self {
+        Self { shift: true, load_store: LoadStore::Unchanged, jump: true, vf_reset: false, clipping: true, display_wait: false }
+    }
+
+    pub const fn xo_chip() -> Self {
+        Self { shift: false, load_store: LoadStore::Increment, jump: false, vf_reset: false, clipping: false, display_wait: false }
+    }
+
+    /**
+     * The quirks of the interpreter each variant is most commonly written against
+     */
+    pub const fn for_variant(variant: Variant) -> Self {
+        match variant {
+            Variant::Chip8     => Self::cosmac_vip(),
+            Variant::SuperChip => Self::super_chip(),
+            Variant::XoChip    => Self::xo_chip(),
+        }
+    }
+
+    /**
+     * Pack the flags into a byte, in field order starting from bit 0. Bit 1 is set if FX55/FX65
+     * leave I unchanged, and bit 6 if they increment it by X, so older movies and saves still load
+     */
+    pub fn to_bits(&self) -> u8 {
+        let unchanged = self.load_store == LoadStore::Unchanged;
+        let increment_by_x = self.load_store == LoadStore::IncrementByX;
+        [self.shift, unchanged, self.jump, self.vf_reset, self.clipping, self.display_wait, increment_by_x]
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (i, flag)| bits | ((*flag as u8) << i))
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        Self {
+            shift: bits & 0x01 != 0,
+            load_store: match (bits & 0x02 != 0, bits & 0x40 != 0) {
+                (true, _)      => LoadStore::Unchanged,
+                (false, true)  => LoadStore::IncrementByX,
+                (false, false) => LoadStore::Increment
+            },
+            jump: bits & 0x04 != 0,
+            vf_reset: bits & 0x08 != 0,
+            clipping: bits & 0x10 != 0,
+            display_wait: bits & 0x20 != 0
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn from_name(name: &str) -> Option<Quirks> {
+        match name.to_lowercase().as_str() {
+            "vip" | "cosmac-vip"                 => Some(Self::cosmac_vip()),
+            "chip48" | "chip-48"                 => Some(Self::chip48()),
+            "schip" | "superchip" | "super-chip" => Some(Self::super_chip()),
+            "xochip" | "xo-chip"                 => Some(Self::xo_chip()),
+            _                                    => None
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::VecDeque;
+
+/**
+ * History of recent save states, used to step gameplay backwards one frame at a time.
+ *
+ * Only the newest state is kept in full. Each older state is stored as the difference from the
+ * state after it (the two XORed together, with runs of unchanged bytes compressed), which is
+ * usually only a few bytes since most of memory doesn't change from frame to frame.
+ */
+#[derive(Debug)]
+pub struct RewindBuffer {
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>, // Oldest first
+    capacity: usize,           // Maximum number of frames which can be rewound
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self { latest: None, deltas: VecDeque::new(), capacity }
+    }
+
+    /**
+     * Record the state at the end of a frame
+     */
+    pub fn push(&mut self, state: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if let Some(latest) = self.latest.take() {
+            // States of different sizes can't be diffed, so start the history again
+            match latest.len() == state.len() {
+                true  => self.deltas.push_back(encode_delta(&latest, &state)),
+                false => self.deltas.clear()
+            }
+        }
+
+        while self.deltas.len() > self.capacity {
+            self.deltas.pop_front();
+        }
+
+        self.latest = Some(state);
+    }
+
+    /**
+     * Step back one frame, returning the state before the most recently recorded one. Returns None
+     * once the history has run out
+     */
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let delta = self.deltas.pop_back()?;
+        let latest = self.latest.as_mut()?;
+        apply_delta(latest, &delta);
+        Some(latest.clone())
+    }
+}
+
+/**
+ * Encode the XOR of two equal length states as a series of (unchanged run, changed run) pairs.
+ * Each pair is two big endian u16 lengths followed by the changed bytes
+ */
+fn encode_delta(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    let mut i = 0;
+
+    while i < new.len() {
+        let unchanged_start = i;
+        while i < new.len() && old[i] == new[i] && i - unchanged_start < u16::MAX as usize {
+            i += 1;
+        }
+        let unchanged = i - unchanged_start;
+
+        let changed_start = i;
+        while i < new.len() && old[i] != new[i] && i - changed_start < u16::MAX as usize {
+            i += 1;
+        }
+
+        delta.extend_from_slice(&(unchanged as u16).to_be_bytes());
+        delta.extend_from_slice(&((i - changed_start) as u16).to_be_bytes());
+        delta.extend(old[changed_start..i].iter().zip(&new[changed_start..i]).map(|(a, b)| a ^ b));
+    }
+
+    delta
+}
+
+/**
+ * XOR a delta back into a state. As XOR is its own inverse, this turns either state into the other
+ */
+fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let mut i = 0;
+    let mut pos = 0;
+
+    while pos + 4 <= delta.len() {
+        let unchanged = u16::from_be_bytes([delta[pos], delta[pos + 1]]) as usize;
+        let changed = u16::from_be_bytes([delta[pos + 2], delta[pos + 3]]) as usize;
+        pos += 4;
+        i += unchanged;
+
+        for byte in &delta[pos..pos + changed] {
+            state[i] ^= byte;
+            i += 1;
+        }
+        pos += changed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_state(frame: u8) -> Vec<u8> {
+        // Mostly unchanged from frame to frame, like a real state
+        let mut state = vec![0xAA; 100];
+        state[10] = frame;
+        state[50..50 + frame as usize].fill(frame);
+        state
+    }
+
+    #[test]
+    fn steps_back_through_earlier_states() {
+        let mut rewind = RewindBuffer::new(10);
+        for frame in 0..5 {
+            rewind.push(get_state(frame));
+        }
+
+        for frame in (0..4).rev() {
+            assert_eq!(rewind.pop(), Some(get_state(frame)));
+        }
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn forgets_states_past_its_capacity() {
+        let mut rewind = RewindBuffer::new(2);
+        for frame in 0..5 {
+            rewind.push(get_state(frame));
+        }
+
+        assert_eq!(rewind.pop(), Some(get_state(3)));
+        assert_eq!(rewind.pop(), Some(get_state(2)));
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn restarts_when_the_state_size_changes() {
+        let mut rewind = RewindBuffer::new(10);
+        rewind.push(get_state(0));
+        rewind.push(vec![1, 2, 3]);
+        rewind.push(vec![4, 5, 6]);
+
+        assert_eq!(rewind.pop(), Some(vec![1, 2, 3]));
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn encodes_long_runs() {
+        // Runs longer than a u16 length have to be split
+        let old = vec![0; 200_000];
+        let mut new = old.clone();
+        new[100_000..180_000].fill(1);
+
+        let mut state = old.clone();
+        apply_delta(&mut state, &encode_delta(&old, &new));
+        assert_eq!(state, new);
+    }
+}
@@ -0,0 +1,59 @@
+/**
+ * Seedable random number generator used by CXNN. Runs of a ROM with the same seed and the same
+ * inputs produce exactly the same random numbers, which replays and save states rely on.
+ *
+ * This is SplitMix64 - it's tiny, works with any seed (including 0) and the whole generator is a
+ * single u64, so it's cheap to snapshot.
+ */
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub const fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /**
+     * Pick a seed for runs which don't need to be reproduced
+     */
+    #[cfg(feature = "std")]
+    pub fn random_seed() -> u64 {
+        rand::random::<u64>()
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        // The high bits are the best mixed
+        (self.next_u64() >> 56) as u8
+    }
+
+    pub fn get_state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn set_state(&mut self, state: u64) {
+        self.state = state;
+    }
+}
+
+/**
+ * Anything which can supply CXNN's random numbers
+ */
+pub trait RandomSource {
+    fn next_u8(&mut self) -> u8;
+}
+
+impl RandomSource for Rng {
+    fn next_u8(&mut self) -> u8 {
+        Rng::next_u8(self)
+    }
+}
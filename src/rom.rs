@@ -1,4 +1,7 @@
 use std::fs;
+use std::io;
+
+use crate::{assembler::assemble, error::EmulatorError};
 
 #[derive(Debug)]
 pub struct Rom {
@@ -6,17 +9,48 @@ pub struct Rom {
 }
 
 impl Rom {
-    pub fn new(file: &str) -> Rom {
-        let contents = fs::read(file)
-            .expect("Something went wrong reading the file");
-
-        println!("Loading ROM {}", file);
-
-        Rom {
+    pub fn new(file: &str) -> io::Result<Rom> {
+        let contents = fs::read(file)?;
+        Ok(Rom {
             data: contents
+        })
+    }
+
+    /**
+     * Load a ROM file, assembling it first if it's Octo style source (.8o)
+     */
+    pub fn load(file: &str) -> Result<Rom, EmulatorError> {
+        match file.ends_with(".8o") {
+            true  => Rom::from_source(file),
+            false => Ok(Rom::new(file)?)
         }
     }
 
+    pub fn from_bytes(data: Vec<u8>) -> Rom {
+        Rom { data }
+    }
+
+    /**
+     * Assemble an Octo style source file (.8o) into a ROM
+     */
+    pub fn from_source(file: &str) -> Result<Rom, EmulatorError> {
+        let source = fs::read_to_string(file)?;
+        Ok(Rom {
+            data: assemble(&source)?
+        })
+    }
+
+    /**
+     * 64-bit FNV-1a hash of the ROM, used to check that a movie is played back on the ROM it was recorded with
+     */
+    pub fn get_hash(&self) -> u64 {
+        self.data.iter().fold(0xCBF29CE484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001B3))
+    }
+
+    pub fn get_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
     pub fn get_byte(&self, addr: usize) -> u8 {
         self.data[addr]
     }
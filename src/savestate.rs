@@ -0,0 +1,245 @@
+use crate::error::EmulatorError;
+
+/*
+ * Save state format
+ *
+ * All multi-byte values are big endian. A state is only valid for the variant it was saved with,
+ * and every state for a variant is the same size, so states can be compared byte for byte.
+ *
+ * Offset  Size        Field
+ * ------  ----        -----
+ * 0       4           Magic number "RCSS"
+ * 4       2           Format version (SAVE_STATE_VERSION)
+ * 6       1           Variant - 0 CHIP-8, 1 SUPER-CHIP, 2 XO-CHIP
+ * 7       1           Quirks - bit 0 shift, 1 load/store leaves I unchanged, 2 jump, 3 VF reset,
+ *                     4 clipping, 5 display wait, 6 load/store increments I by X
+ * 8       2           Program counter
+ * 10      16          V0-VF
+ * 26      2           I
+ * 28      1           Delay timer
+ * 29      1           Sound timer
+ * 30      1           Stack depth
+ * 31      32          Stack, 16 addresses with unused entries set to 0
+ * 63      1           Flags - bit 0 halted, 1 waiting for vblank, 2 exited
+ * 64      16          RPL user flags
+ * 80      1           Audio pitch
+ * 81      8           Random number generator state
+ * 89      4           Memory size (N)
+ * 93      N           Memory
+ * 93+N    1           Display - 0 low resolution, 1 high resolution
+ * 94+N    1           Display - selected planes
+ * 95+N    8192        Display - 128 columns of 64 pixels, one byte per pixel holding a bit per plane
+ * 8287+N  2           Keyboard - bit N set while key N is held
+ *
+ * Version history:
+ * 1 - Initial format
+ * 2 - Added the random number generator state
+ * 3 - Added quirks bit 6 for CHIP-48's load/store, which version 2 readers would take as the
+ *     VIP's increment
+ *
+ * The version is increased whenever the layout changes. States from other versions are rejected
+ * rather than being loaded into a machine they don't describe.
+ */
+
+// Identifies a file as a rusty-chip save state
+const MAGIC: &[u8; 4] = b"RCSS";
+
+pub const SAVE_STATE_VERSION: u16 = 3;
+
+/**
+ * Builds up a save state, starting with the header
+ */
+#[derive(Debug)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        let mut writer = Self { data: Vec::new() };
+        writer.write_bytes(MAGIC);
+        writer.write_u16(SAVE_STATE_VERSION);
+        writer
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/**
+ * Reads a save state back, checking the header before anything else is read
+ */
+#[derive(Debug)]
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, EmulatorError> {
+        let mut reader = Self { data, position: 0 };
+        if reader.read_bytes(MAGIC.len())? != MAGIC {
+            return Err(EmulatorError::InvalidSaveState { reason: "not a save state" });
+        }
+
+        let version = reader.read_u16()?;
+        if version != SAVE_STATE_VERSION {
+            return Err(EmulatorError::UnsupportedSaveStateVersion { version });
+        }
+
+        Ok(reader)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, EmulatorError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, EmulatorError> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, EmulatorError> {
+        let bytes = self.read_bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, EmulatorError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.read_bytes(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, EmulatorError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(EmulatorError::InvalidSaveState { reason: "invalid flag value" })
+        }
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], EmulatorError> {
+        let end = self.position + len;
+        if end > self.data.len() {
+            return Err(EmulatorError::InvalidSaveState { reason: "state is truncated" });
+        }
+
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    /**
+     * Check that the whole state has been read - anything left over means it wasn't the state we expected
+     */
+    pub fn finish(self) -> Result<(), EmulatorError> {
+        match self.position == self.data.len() {
+            true  => Ok(()),
+            false => Err(EmulatorError::InvalidSaveState { reason: "unexpected data at the end of the state" })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chip8::Chip8, quirks::Quirks, variant::Variant};
+
+    fn get_running_chip8() -> Chip8 {
+        // Draws random sprites forever, so every frame changes the registers, screen and RNG
+        let program = [0xC0, 0xFF, 0xC1, 0xFF, 0xA2, 0x00, 0xD0, 0x15, 0x12, 0x00];
+        let mut chip8 = Chip8::new(Variant::Chip8, Quirks::for_variant(Variant::Chip8), 1);
+        chip8.load_rom_bytes(&program).unwrap();
+        for _ in 0..10 {
+            chip8.run_frame(&[]).unwrap();
+        }
+        chip8
+    }
+
+    #[test]
+    fn reads_back_what_was_written() {
+        let mut writer = StateWriter::new();
+        writer.write_u8(0x12);
+        writer.write_u16(0x3456);
+        writer.write_u32(0x789ABCDE);
+        writer.write_u64(0x0123456789ABCDEF);
+        writer.write_bool(true);
+        writer.write_bytes(&[1, 2, 3]);
+        let data = writer.finish();
+
+        let mut reader = StateReader::new(&data).unwrap();
+        assert_eq!(reader.read_u8().unwrap(), 0x12);
+        assert_eq!(reader.read_u16().unwrap(), 0x3456);
+        assert_eq!(reader.read_u32().unwrap(), 0x789ABCDE);
+        assert_eq!(reader.read_u64().unwrap(), 0x0123456789ABCDEF);
+        assert!(reader.read_bool().unwrap());
+        assert_eq!(reader.read_bytes(3).unwrap(), &[1, 2, 3]);
+        assert!(reader.finish().is_ok());
+    }
+
+    #[test]
+    fn rejects_other_files_and_versions() {
+        assert!(matches!(StateReader::new(b"RCSX\x00\x03"), Err(EmulatorError::InvalidSaveState { .. })));
+        assert!(matches!(StateReader::new(b"RCSS\x00\x02"), Err(EmulatorError::UnsupportedSaveStateVersion { version: 2 })));
+        assert!(matches!(StateReader::new(b"RCS"), Err(EmulatorError::InvalidSaveState { .. })));
+    }
+
+    #[test]
+    fn restores_the_machine() {
+        let mut chip8 = get_running_chip8();
+        let state = chip8.save_state();
+        chip8.run_frame(&[]).unwrap();
+        let later = chip8.save_state();
+        assert_ne!(state, later);
+
+        chip8.load_state(&state).unwrap();
+        assert_eq!(chip8.save_state(), state);
+
+        // Running on from the restored state repeats exactly what happened the first time
+        chip8.run_frame(&[]).unwrap();
+        assert_eq!(chip8.save_state(), later);
+    }
+
+    #[test]
+    fn leaves_the_machine_alone_if_the_state_is_bad() {
+        let mut chip8 = get_running_chip8();
+        let state = chip8.save_state();
+
+        assert!(chip8.load_state(&state[..state.len() - 1]).is_err());
+        let mut extended = state.clone();
+        extended.push(0);
+        assert!(chip8.load_state(&extended).is_err());
+        assert_eq!(chip8.save_state(), state);
+    }
+}
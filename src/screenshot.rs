@@ -0,0 +1,254 @@
+use std::fs;
+use std::io;
+
+use crate::{display::Display, palette::Palette};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png, // In the palette's colours
+    Pbm, // Plain (P1) bitmap - any colour other than the background counts as lit
+}
+
+impl ImageFormat {
+    pub fn from_name(name: &str) -> Option<ImageFormat> {
+        match name.to_lowercase().as_str() {
+            "png" => Some(ImageFormat::Png),
+            "pbm" => Some(ImageFormat::Pbm),
+            _     => None
+        }
+    }
+
+    /**
+     * Pick the format from a file's extension
+     */
+    pub fn from_file(file: &str) -> Option<ImageFormat> {
+        file.rsplit_once('.').and_then(|(_, extension)| ImageFormat::from_name(extension))
+    }
+}
+
+/**
+ * Write the screen to an image, with each pixel scaled up to a square of scale x scale pixels
+ */
+pub fn save_screenshot(display: &Display, file: &str, format: ImageFormat, scale: usize, palette: &Palette) -> io::Result<()> {
+    let image = match format {
+        ImageFormat::Png => encode_png(display, scale, palette),
+        ImageFormat::Pbm => encode_pbm(display, scale).into_bytes()
+    };
+    fs::write(file, image)
+}
+
+/**
+ * Plain (P1) PBM, where 1 is black - so lit pixels are written as 0 to keep the CHIP-8's colours
+ */
+pub fn encode_pbm(display: &Display, scale: usize) -> String {
+    let width = display.get_width() * scale;
+    let height = display.get_height() * scale;
+    let mut image = format!("P1\n{} {}\n", width, height);
+    for y in 0..height {
+        let row: Vec<&str> = (0..width)
+            .map(|x| match display.get_pixel(x / scale, y / scale) {
+                0 => "1",
+                _ => "0"
+            })
+            .collect();
+
+        // PBM lines shouldn't be longer than 70 characters
+        for chunk in row.chunks(32) {
+            image.push_str(&chunk.join(" "));
+            image.push('\n');
+        }
+    }
+    image
+}
+
+/**
+ * PNG using the palette as its colour table. Each pixel is already a colour index, so the image is
+ * stored as 2 bits per pixel without any compression - even a scaled up high resolution screen is
+ * only a couple of hundred kilobytes
+ */
+pub fn encode_png(display: &Display, scale: usize, palette: &Palette) -> Vec<u8> {
+    let width = display.get_width() * scale;
+    let height = display.get_height() * scale;
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[
+        2, // Bits per pixel
+        3, // Colour type - indexed
+        0, // Compression - deflate
+        0, // Filter method
+        0  // No interlacing
+    ]);
+
+    let colors: Vec<u8> = palette.colors.iter().flat_map(|(r, g, b)| [*r, *g, *b]).collect();
+
+    // Each row starts with its filter type (0 - none), followed by four pixels to a byte
+    let mut pixels = Vec::with_capacity(height * (1 + width.div_ceil(4)));
+    for y in 0..height {
+        pixels.push(0);
+        for x in (0..width).step_by(4) {
+            let byte = (0..4)
+                .filter(|offset| x + offset < width)
+                .fold(0u8, |byte, offset| byte | (display.get_pixel((x + offset) / scale, y / scale) << (6 - offset * 2)));
+            pixels.push(byte);
+        }
+    }
+
+    let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+    write_png_chunk(&mut png, b"IHDR", &header);
+    write_png_chunk(&mut png, b"PLTE", &colors);
+    write_png_chunk(&mut png, b"IDAT", &encode_zlib_stored(&pixels));
+    write_png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_png_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+
+    // The checksum covers the type as well as the data
+    let crc = get_crc32(chunk_type.iter().chain(data.iter()));
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/**
+ * Wrap data in a zlib stream made of uncompressed deflate blocks, which can hold up to 65535 bytes each
+ */
+fn encode_zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+
+    // Even empty data needs one (final) block
+    if blocks.peek().is_none() {
+        zlib.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        zlib.push(is_final as u8);
+        zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+
+    zlib.extend_from_slice(&get_adler32(data).to_be_bytes());
+    zlib
+}
+
+fn get_crc32<'a>(data: impl Iterator<Item = &'a u8>) -> u32 {
+    let crc = data.fold(0xFFFFFFFFu32, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| match crc & 1 {
+            1 => (crc >> 1) ^ 0xEDB88320,
+            _ => crc >> 1
+        })
+    });
+    !crc
+}
+
+fn get_adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), byte| {
+        let a = (a + *byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_display() -> Display {
+        let mut display = Display::new();
+        display.set_pixel(0, 0, 1, 1);
+        display.set_pixel(63, 31, 2, 1);
+        display.set_pixel(10, 5, 3, 1);
+        display
+    }
+
+    /**
+     * Split a PNG into its chunks, checking each one's CRC
+     */
+    fn read_png_chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(&png[..8], &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+        let mut chunks = Vec::new();
+        let mut pos = 8;
+        while pos < png.len() {
+            let len = u32::from_be_bytes(png[pos..pos + 4].try_into().unwrap()) as usize;
+            let chunk_type: [u8; 4] = png[pos + 4..pos + 8].try_into().unwrap();
+            let data = png[pos + 8..pos + 8 + len].to_vec();
+            let crc = u32::from_be_bytes(png[pos + 8 + len..pos + 12 + len].try_into().unwrap());
+            assert_eq!(crc, get_crc32(png[pos + 4..pos + 8 + len].iter()));
+            chunks.push((chunk_type, data));
+            pos += 12 + len;
+        }
+        chunks
+    }
+
+    /**
+     * Undo encode_zlib_stored, checking the block lengths and checksum
+     */
+    fn read_zlib_stored(zlib: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut pos = 2;
+        loop {
+            let is_final = zlib[pos] & 1 == 1;
+            let len = u16::from_le_bytes([zlib[pos + 1], zlib[pos + 2]]);
+            assert_eq!(!len, u16::from_le_bytes([zlib[pos + 3], zlib[pos + 4]]));
+            data.extend_from_slice(&zlib[pos + 5..pos + 5 + len as usize]);
+            pos += 5 + len as usize;
+            if is_final {
+                break;
+            }
+        }
+        assert_eq!(&zlib[pos..], &get_adler32(&data).to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn encodes_png_in_the_palette_colours() {
+        let palette = Palette::default();
+        let png = encode_png(&get_display(), 3, &palette);
+        let chunks = read_png_chunks(&png);
+        let types: Vec<&[u8; 4]> = chunks.iter().map(|(chunk_type, _)| chunk_type).collect();
+        assert_eq!(types, vec![b"IHDR", b"PLTE", b"IDAT", b"IEND"]);
+
+        assert_eq!(&chunks[0].1[..8], &[0, 0, 0, 192, 0, 0, 0, 96]);
+        let colors: Vec<u8> = palette.colors.iter().flat_map(|(r, g, b)| [*r, *g, *b]).collect();
+        assert_eq!(chunks[1].1, colors);
+
+        // 192 pixels at 2 bits each, plus the filter byte
+        let pixels = read_zlib_stored(&chunks[2].1);
+        let row_size = 1 + 192 / 4;
+        assert_eq!(pixels.len(), row_size * 96);
+        let get_pixel = |x: usize, y: usize| (pixels[y * row_size + 1 + x / 4] >> (6 - (x % 4) * 2)) & 0x3;
+        assert_eq!(get_pixel(2, 2), 1);
+        assert_eq!(get_pixel(3, 3), 0);
+        assert_eq!(get_pixel(191, 95), 2);
+        assert_eq!(get_pixel(30, 15), 3);
+    }
+
+    #[test]
+    fn splits_large_images_into_several_blocks() {
+        let data: Vec<u8> = (0..200_000).map(|i| i as u8).collect();
+        assert_eq!(read_zlib_stored(&encode_zlib_stored(&data)), data);
+        assert!(read_zlib_stored(&encode_zlib_stored(&[])).is_empty());
+    }
+
+    #[test]
+    fn encodes_pbm_with_lit_pixels_as_white() {
+        let pbm = encode_pbm(&get_display(), 1);
+        let mut lines = pbm.lines();
+        assert_eq!(lines.next(), Some("P1"));
+        assert_eq!(lines.next(), Some("64 32"));
+
+        let pixels: Vec<&str> = lines.flat_map(|line| line.split(' ')).collect();
+        assert_eq!(pixels.len(), 64 * 32);
+        assert_eq!(pixels[0], "0");
+        assert_eq!(pixels[1], "1");
+        assert_eq!(pixels[5 * 64 + 10], "0");
+        assert_eq!(pixels[31 * 64 + 63], "0");
+        assert!(pbm.lines().all(|line| line.len() <= 70));
+    }
+}
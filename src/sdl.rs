@@ -0,0 +1,222 @@
+use std::collections::{HashMap, HashSet};
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+use sdl2::{EventPump, Sdl};
+use crate::{audio::{AudioSettings, Oscillator}, display::Display, frontend::{AudioSink, InputEvent, InputSource, VideoSink}, keymap::KeyMap, palette::Palette, util::{DISPLAY_HEIGHT, DISPLAY_WIDTH}};
+
+// Requested audio format - SDL may give us something slightly different
+const SAMPLE_RATE: i32 = 44100;
+const SAMPLES_PER_CALLBACK: u16 = 512;
+
+/**
+ * Window, keyboard and sound handling using SDL. Each part is separate so they can be passed to
+ * `Chip8::run` independently
+ */
+pub struct SdlFrontend {
+    pub video: SdlVideo,
+    pub input: SdlInput,
+    pub audio: SdlAudio,
+    _sdl_context: Sdl, // SDL is shut down when this is dropped
+}
+
+pub struct SdlVideo {
+    canvas: WindowCanvas,
+    palette: Palette,
+}
+
+pub struct SdlInput {
+    event_pump: EventPump,
+    key_map: HashMap<Keycode, u8>,
+    held: HashSet<Keycode>, // Host keys held down, so a CHIP-8 key with several isn't let go of too soon
+}
+
+pub struct SdlAudio {
+    device: Option<AudioDevice<Beeper>>, // None if there's no audio device, in which case nothing is played
+    playing: bool,
+}
+
+struct Beeper {
+    oscillator: Oscillator,
+}
+
+impl AudioCallback for Beeper {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.oscillator.fill(out);
+    }
+}
+
+impl SdlFrontend {
+    pub fn new(audio_settings: AudioSettings, palette: Palette, key_map: &KeyMap) -> Result<Self, String> {
+        let sdl_context = sdl2::init()?;
+        let video_subsystem = sdl_context.video()?;
+        let window = video_subsystem
+            .window("Rusty Chip", DISPLAY_WIDTH as u32 * 10, DISPLAY_HEIGHT as u32 * 10)
+            .position_centered()
+            .opengl()
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        // Frames are paced by the emulator's clock, so presenting mustn't wait for the monitor as well
+        let canvas = window
+            .into_canvas()
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        // Sound is optional - carry on silently if it can't be opened
+        let desired_spec = AudioSpecDesired { freq: Some(SAMPLE_RATE), channels: Some(1), samples: Some(SAMPLES_PER_CALLBACK) };
+        let device = sdl_context.audio().and_then(|audio_subsystem| {
+            audio_subsystem.open_playback(None, &desired_spec, |spec| {
+                Beeper { oscillator: Oscillator::new(audio_settings, spec.freq as u32) }
+            })
+        });
+        if let Err(e) = &device {
+            eprintln!("Sound is unavailable: {}", e);
+        }
+
+        let event_pump = sdl_context.event_pump()?;
+        Ok(Self {
+            video: SdlVideo { canvas, palette },
+            input: SdlInput { event_pump, key_map: get_key_map(key_map), held: HashSet::new() },
+            audio: SdlAudio { device: device.ok(), playing: false },
+            _sdl_context: sdl_context
+        })
+    }
+}
+
+impl InputSource for SdlInput {
+    fn poll_events(&mut self) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        for event in self.event_pump.poll_iter() {
+            let input = match event {
+                Event::Quit { .. } |
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. }    => Some(InputEvent::Quit),
+                Event::KeyDown { keycode: Some(Keycode::F12), .. }       => Some(InputEvent::Debug),
+                Event::KeyDown { keycode: Some(Keycode::Backspace), .. } => Some(InputEvent::RewindStart),
+                Event::KeyUp { keycode: Some(Keycode::Backspace), .. }   => Some(InputEvent::RewindStop),
+                Event::KeyDown { keycode: Some(Keycode::F9), .. }        => Some(InputEvent::ToggleMute),
+                Event::KeyDown { keycode: Some(Keycode::F10), .. }       => Some(InputEvent::ToggleVideo),
+                Event::KeyDown { keycode: Some(Keycode::F11), .. }       => Some(InputEvent::Screenshot),
+                // F1-F8 save to a numbered slot, and load from it with shift held
+                Event::KeyDown { keycode: Some(keycode), keymod, .. } if get_state_slot(keycode).is_some() => {
+                    let slot = get_state_slot(keycode).unwrap();
+                    match keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        true  => Some(InputEvent::LoadState(slot)),
+                        false => Some(InputEvent::SaveState(slot))
+                    }
+                },
+                Event::KeyDown { keycode: Some(keycode), .. } => {
+                    self.held.insert(keycode);
+                    self.key_map.get(&keycode).map(|key| InputEvent::KeyDown(*key))
+                },
+                Event::KeyUp { keycode: Some(keycode), .. }   => {
+                    self.held.remove(&keycode);
+                    self.key_map.get(&keycode).copied().filter(|key| !is_held(&self.key_map, &self.held, *key)).map(InputEvent::KeyUp)
+                },
+                _ => None
+            };
+            events.extend(input);
+        }
+        events
+    }
+}
+
+impl VideoSink for SdlVideo {
+    fn present(&mut self, display: &Display) {
+        self.canvas.set_draw_color(self.get_color(0));
+        self.canvas.clear();
+
+        let width = display.get_width();
+        let height = display.get_height();
+        let scale = self.get_scale(display) as u32;
+
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = display.get_pixel(x, y);
+                if pixel > 0 {
+                    self.canvas.set_draw_color(self.get_color(pixel));
+                    let rect = Rect::new(x as i32 * scale as i32, y as i32 * scale as i32, scale, scale);
+                    self.canvas.fill_rect(rect).unwrap();
+                }
+            }
+        }
+
+        self.canvas.present();
+    }
+
+    fn get_palette(&self) -> Palette {
+        self.palette
+    }
+
+    /**
+     * Each pixel is scaled to fill the window, whichever resolution the display is currently in
+     */
+    fn get_scale(&self, display: &Display) -> usize {
+        let (window_width, _) = self.canvas.output_size().unwrap();
+        window_width as usize / display.get_width()
+    }
+}
+
+impl SdlVideo {
+    fn get_color(&self, pixel: u8) -> Color {
+        let (r, g, b) = self.palette.get_color(pixel);
+        Color::RGB(r, g, b)
+    }
+}
+
+impl AudioSink for SdlAudio {
+    fn set_playing(&mut self, playing: bool) {
+        if playing == self.playing {
+            return;
+        }
+        self.playing = playing;
+
+        if let Some(device) = &self.device {
+            match playing {
+                true  => device.resume(),
+                false => device.pause()
+            }
+        }
+    }
+}
+
+fn get_state_slot(keycode: Keycode) -> Option<u8> {
+    match keycode {
+        Keycode::F1 => Some(1),
+        Keycode::F2 => Some(2),
+        Keycode::F3 => Some(3),
+        Keycode::F4 => Some(4),
+        Keycode::F5 => Some(5),
+        Keycode::F6 => Some(6),
+        Keycode::F7 => Some(7),
+        Keycode::F8 => Some(8),
+        _           => None
+    }
+}
+
+/**
+ * Whether any of the host keys for a CHIP-8 key are still held down
+ */
+fn is_held(key_map: &HashMap<Keycode, u8>, held: &HashSet<Keycode>, key: u8) -> bool {
+    held.iter().any(|keycode| key_map.get(keycode) == Some(&key))
+}
+
+/**
+ * Key maps use SDL's own key names, so they can be looked up directly
+ */
+fn get_key_map(key_map: &KeyMap) -> HashMap<Keycode, u8> {
+    key_map.get_bindings().iter()
+        .filter_map(|(name, key)| match Keycode::from_name(name) {
+            Some(keycode) => Some((keycode, *key)),
+            None          => {
+                eprintln!("Unknown key {} in key map", name);
+                None
+            }
+        })
+        .collect()
+}
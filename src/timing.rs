@@ -0,0 +1,120 @@
+use crate::instruction::Instruction;
+
+/*
+ * Approximate COSMAC VIP timings, from the behaviour of the original CHIP-8 interpreter.
+ *
+ * The VIP's CDP1802 runs at 1.76064 MHz and takes 8 clock cycles per machine cycle, giving 3668
+ * machine cycles per 60 Hz frame. The display's DMA and the interrupt routine (which counts the
+ * timers down) take their share of each frame, and the interpreter gets the rest. Every instruction
+ * pays for being fetched and decoded on top of its own cost, which for some instructions depends on
+ * what they do.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingMode {
+    Fixed,     // A flat number of instructions per second
+    CosmacVip, // Each instruction takes as long as it did on the COSMAC VIP
+}
+
+impl TimingMode {
+    #[cfg(feature = "std")]
+    pub fn from_name(name: &str) -> Option<TimingMode> {
+        match name.to_lowercase().as_str() {
+            "fixed"              => Some(TimingMode::Fixed),
+            "vip" | "cosmac-vip" => Some(TimingMode::CosmacVip),
+            _                    => None
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            TimingMode::Fixed     => "fixed",
+            TimingMode::CosmacVip => "vip",
+        }
+    }
+}
+
+// Machine cycles in each frame
+const VIP_CYCLES_PER_FRAME: u32 = 3668;
+
+// 128 lines of 8 bytes of display DMA, plus the interrupt routine
+const VIP_INTERRUPT_CYCLES: u32 = 128 * 8 + 46;
+
+// Machine cycles left for the interpreter in each frame
+pub const VIP_INSTRUCTION_CYCLES_PER_FRAME: u32 = VIP_CYCLES_PER_FRAME - VIP_INTERRUPT_CYCLES;
+
+// Fetching and decoding an instruction, before it does anything
+const VIP_FETCH_CYCLES: u32 = 40;
+
+// Extra cost of skipping the next instruction
+const VIP_SKIP_CYCLES: u32 = 4;
+
+/**
+ * Machine cycles the VIP took to run an instruction. v_registers must be the registers from before
+ * the instruction ran
+ */
+pub fn get_vip_cycles(instr: Instruction, v_registers: &[u8; 16], skipped: bool) -> u32 {
+    let skip = match skipped {
+        true  => VIP_SKIP_CYCLES,
+        false => 0
+    };
+
+    let cycles = match instr {
+        // Clearing the screen writes all 256 bytes of display memory
+        Instruction::ClearScreen                    => 3078,
+        Instruction::Return                         => 10,
+        Instruction::Jump { .. }                    => 12,
+        Instruction::Call { .. }                    => 26,
+        Instruction::SkipIfEqual { .. } |
+        Instruction::SkipIfNotEqual { .. }          => 10 + skip,
+        Instruction::SkipIfRegistersEqual { .. } |
+        Instruction::SkipIfRegistersNotEqual { .. } => 14 + skip,
+        Instruction::SetRegister { .. }             => 6,
+        Instruction::AddValue { .. }                => 10,
+        // The 8XYN instructions all share a routine which builds and runs the 1802 instruction
+        Instruction::Copy { .. } |
+        Instruction::Or { .. } |
+        Instruction::And { .. } |
+        Instruction::Xor { .. } |
+        Instruction::Add { .. } |
+        Instruction::Subtract { .. } |
+        Instruction::ShiftRight { .. } |
+        Instruction::SubtractReverse { .. } |
+        Instruction::ShiftLeft { .. }               => 44,
+        Instruction::SetI { .. }                    => 12,
+        Instruction::JumpWithOffset { .. }          => 22,
+        Instruction::Random { .. }                  => 36,
+        Instruction::Draw { x, rows, .. }           => get_vip_draw_cycles(v_registers[x as usize], rows),
+        Instruction::SkipIfKeyPressed { .. } |
+        Instruction::SkipIfKeyNotPressed { .. }     => 14 + skip,
+        Instruction::GetDelayTimer { .. }           => 10,
+        Instruction::AwaitKey { .. }                => 18,
+        Instruction::SetDelayTimer { .. } |
+        Instruction::SetSoundTimer { .. }           => 10,
+        Instruction::AddToI { .. }                  => 16,
+        Instruction::SetIToFont { .. }              => 16,
+        // BCD conversion counts each digit down, so bigger digits take longer
+        Instruction::StoreBcd { x }                 => {
+            let value = v_registers[x as usize] as u32;
+            80 + 16 * (value / 100 + value / 10 % 10 + value % 10)
+        },
+        Instruction::StoreRegisters { x } |
+        Instruction::LoadRegisters { x }            => 14 + 14 * (x as u32 + 1),
+        // Instructions the VIP never had - count them as the cheapest instruction
+        _                                           => 6
+    };
+
+    VIP_FETCH_CYCLES + cycles
+}
+
+/**
+ * Each row of a sprite costs more when it isn't aligned to a byte of display memory, as it has to be
+ * shifted and written across two bytes
+ */
+fn get_vip_draw_cycles(x: u8, rows: u8) -> u32 {
+    let row_cycles = match x.is_multiple_of(8) {
+        true  => 46,
+        false => 68
+    };
+    26 + rows as u32 * row_cycles
+}
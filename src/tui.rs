@@ -0,0 +1,263 @@
+use std::io::{self, Stdout, Write};
+use std::time::{Duration, Instant};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use crate::{display::Display, frontend::{AudioSink, InputEvent, InputSource, VideoSink}, keymap::KeyMap, palette::Palette};
+
+// Most terminals only report key presses, so a key counts as held until this long after its last
+// press or auto-repeat
+const KEY_HOLD: Duration = Duration::from_millis(200);
+
+/**
+ * Frontend for a terminal, e.g. over SSH. Each character cell shows two pixels, one above the other,
+ * using the upper half block coloured with the top pixel on a background of the bottom one.
+ * The terminal is put back to normal when this is dropped
+ */
+pub struct TuiFrontend {
+    pub video: TuiVideo,
+    pub input: TuiInput,
+    pub audio: TuiAudio,
+    enhanced_keyboard: bool, // Whether the terminal was asked to report key releases
+}
+
+pub struct TuiVideo {
+    stdout: Stdout,
+    last_frame: Vec<u8>, // Pixels drawn last time, so an unchanged screen isn't sent again
+    last_size: (usize, usize),
+    palette: Palette,
+    status: String, // Last status message, shown on the line below the picture
+    status_drawn: bool,
+}
+
+pub struct TuiInput {
+    key_map: KeyMap,
+    reports_releases: bool,
+    held: [Option<Instant>; 16], // When each held key will be released if the terminal doesn't say
+    rewind_held: Option<Instant>,
+}
+
+/**
+ * Rings the terminal bell each time the buzzer starts
+ */
+pub struct TuiAudio {
+    playing: bool,
+}
+
+impl TuiFrontend {
+    pub fn new(palette: Palette, key_map: KeyMap) -> io::Result<Self> {
+        let mut stdout = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(stdout, EnterAlternateScreen, Hide, Clear(ClearType::All))?;
+
+        // Terminals which support it can tell us when keys are released, rather than us guessing
+        let enhanced_keyboard = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if enhanced_keyboard {
+            execute!(stdout, PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES))?;
+        }
+
+        Ok(Self {
+            video: TuiVideo { stdout, last_frame: Vec::new(), last_size: (0, 0), palette, status: String::new(), status_drawn: true },
+            input: TuiInput { key_map, reports_releases: enhanced_keyboard, held: [None; 16], rewind_held: None },
+            audio: TuiAudio { playing: false },
+            enhanced_keyboard
+        })
+    }
+}
+
+impl Drop for TuiFrontend {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        if self.enhanced_keyboard {
+            let _ = execute!(stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(stdout, ResetColor, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+impl InputSource for TuiInput {
+    fn poll_events(&mut self) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        while event::poll(Duration::ZERO).unwrap_or(false) {
+            if let Ok(Event::Key(key_event)) = event::read() {
+                self.handle_key(key_event, &mut events);
+            }
+        }
+
+        if self.reports_releases {
+            return events;
+        }
+
+        // Release keys which haven't been pressed again recently
+        let now = Instant::now();
+        for (key, held) in self.held.iter_mut().enumerate() {
+            if held.map(|release_at| now >= release_at).unwrap_or(false) {
+                *held = None;
+                events.push(InputEvent::KeyUp(key as u8));
+            }
+        }
+        if self.rewind_held.map(|release_at| now >= release_at).unwrap_or(false) {
+            self.rewind_held = None;
+            events.push(InputEvent::RewindStop);
+        }
+
+        events
+    }
+}
+
+impl TuiInput {
+    fn handle_key(&mut self, key_event: KeyEvent, events: &mut Vec<InputEvent>) {
+        let released = key_event.kind == KeyEventKind::Release;
+        let shift = key_event.modifiers.contains(KeyModifiers::SHIFT);
+
+        match key_event.code {
+            // Raw mode stops Ctrl+C from interrupting the process, so it has to be handled here
+            KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => events.push(InputEvent::Quit),
+            KeyCode::Esc if !released                      => events.push(InputEvent::Quit),
+            KeyCode::F(9) if !released                     => events.push(InputEvent::ToggleMute),
+            KeyCode::F(10) if !released                    => events.push(InputEvent::ToggleVideo),
+            KeyCode::F(11) if !released                    => events.push(InputEvent::Screenshot),
+            // F1-F8 save to a numbered slot, and load from it with shift held
+            KeyCode::F(slot @ 1..=8) if !released          => match shift {
+                true  => events.push(InputEvent::LoadState(slot)),
+                false => events.push(InputEvent::SaveState(slot))
+            },
+            KeyCode::Backspace                             => {
+                match (released, self.rewind_held.is_some()) {
+                    (true, _)      => events.push(InputEvent::RewindStop),
+                    (false, false) => events.push(InputEvent::RewindStart),
+                    (false, true)  => ()
+                }
+                self.rewind_held = self.get_release_time(released);
+            },
+            _ => {
+                if let Some(key) = self.get_key(key_event.code) {
+                    match (released, self.held[key as usize].is_some()) {
+                        (true, _)      => events.push(InputEvent::KeyUp(key)),
+                        (false, false) => events.push(InputEvent::KeyDown(key)),
+                        (false, true)  => ()
+                    }
+                    self.held[key as usize] = self.get_release_time(released);
+                }
+            }
+        }
+    }
+
+    /**
+     * The CHIP-8 key for a key press, using SDL's names for keys so that key maps work the same in
+     * either frontend
+     */
+    fn get_key(&self, code: KeyCode) -> Option<u8> {
+        let name = match code {
+            KeyCode::Char(' ') => "space".to_string(),
+            KeyCode::Char(c)   => c.to_lowercase().to_string(),
+            KeyCode::Enter     => "return".to_string(),
+            KeyCode::Tab       => "tab".to_string(),
+            KeyCode::Up        => "up".to_string(),
+            KeyCode::Down      => "down".to_string(),
+            KeyCode::Left      => "left".to_string(),
+            KeyCode::Right     => "right".to_string(),
+            _                  => return None
+        };
+
+        // Terminals send number pad keys as the character on them, so number pad bindings have to
+        // match that character as well
+        let keypad_name = match name.as_str() {
+            "return" => "keypad enter".to_string(),
+            _        => format!("keypad {}", name)
+        };
+        self.key_map.get_key(&name).or_else(|| self.key_map.get_key(&keypad_name))
+    }
+
+    /**
+     * When a key which has just been pressed or released should be let go of - only used if the
+     * terminal doesn't report releases itself
+     */
+    fn get_release_time(&self, released: bool) -> Option<Instant> {
+        match released {
+            true  => None,
+            false => Some(Instant::now() + KEY_HOLD)
+        }
+    }
+}
+
+impl VideoSink for TuiVideo {
+    fn present(&mut self, display: &Display) {
+        let width = display.get_width();
+        let height = display.get_height();
+        let mut frame = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                frame.push(display.get_pixel(x, y));
+            }
+        }
+
+        if frame != self.last_frame {
+            self.draw_frame(&frame, width, height);
+            self.last_frame = frame;
+        }
+
+        // The status line goes below the picture, so it's never drawn over
+        if !self.status_drawn {
+            queue!(self.stdout, MoveTo(0, (height / 2) as u16), Clear(ClearType::CurrentLine), Print(&self.status)).unwrap();
+            self.status_drawn = true;
+        }
+        self.stdout.flush().unwrap();
+    }
+
+    fn show_message(&mut self, message: &str) {
+        self.status = message.to_string();
+        self.status_drawn = false;
+    }
+
+    fn get_palette(&self) -> Palette {
+        self.palette
+    }
+}
+
+impl TuiVideo {
+    fn draw_frame(&mut self, frame: &[u8], width: usize, height: usize) {
+        // Switching resolution leaves the old, larger picture behind
+        if (width, height) != self.last_size {
+            queue!(self.stdout, Clear(ClearType::All)).unwrap();
+            self.last_size = (width, height);
+            self.status_drawn = false;
+        }
+
+        let mut colors = None;
+        for row in 0..height / 2 {
+            queue!(self.stdout, MoveTo(0, row as u16)).unwrap();
+            for x in 0..width {
+                let top = frame[row * 2 * width + x];
+                let bottom = frame[(row * 2 + 1) * width + x];
+
+                // Only send colour changes, which keeps the output small enough for a slow connection
+                if colors != Some((top, bottom)) {
+                    let (foreground, background) = (self.get_color(top), self.get_color(bottom));
+                    queue!(self.stdout, SetForegroundColor(foreground), SetBackgroundColor(background)).unwrap();
+                    colors = Some((top, bottom));
+                }
+                queue!(self.stdout, Print('▀')).unwrap();
+            }
+        }
+        queue!(self.stdout, ResetColor).unwrap();
+    }
+
+    fn get_color(&self, pixel: u8) -> Color {
+        let (r, g, b) = self.palette.get_color(pixel);
+        Color::Rgb { r, g, b }
+    }
+}
+
+impl AudioSink for TuiAudio {
+    fn set_playing(&mut self, playing: bool) {
+        if playing && !self.playing {
+            let mut stdout = io::stdout();
+            let _ = stdout.write_all(b"\x07").and_then(|_| stdout.flush());
+        }
+        self.playing = playing;
+    }
+}
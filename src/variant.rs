@@ -0,0 +1,60 @@
+/**
+ * The CHIP-8 dialect being emulated. Later variants are supersets of earlier ones, so variants
+ * can be compared to check whether an instruction is available (e.g. `variant >= Variant::SuperChip`)
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Variant {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Variant {
+    #[cfg(feature = "std")]
+    pub fn from_name(name: &str) -> Option<Variant> {
+        match name.to_lowercase().as_str() {
+            "chip8" | "chip-8"                   => Some(Variant::Chip8),
+            "schip" | "superchip" | "super-chip" => Some(Variant::SuperChip),
+            "xochip" | "xo-chip"                 => Some(Variant::XoChip),
+            _                                    => None
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Variant::Chip8     => "chip8",
+            Variant::SuperChip => "schip",
+            Variant::XoChip    => "xochip",
+        }
+    }
+
+    /**
+     * Number identifying the variant in save states
+     */
+    pub fn get_id(&self) -> u8 {
+        match self {
+            Variant::Chip8     => 0,
+            Variant::SuperChip => 1,
+            Variant::XoChip    => 2,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Variant> {
+        match id {
+            0 => Some(Variant::Chip8),
+            1 => Some(Variant::SuperChip),
+            2 => Some(Variant::XoChip),
+            _ => None
+        }
+    }
+
+    /**
+     * Amount of addressable memory - XO-CHIP extends the address space to the full 16 bits of I
+     */
+    pub const fn get_memory_size(&self) -> usize {
+        match self {
+            Variant::XoChip => 0x10000,
+            _               => 0x1000
+        }
+    }
+}
@@ -0,0 +1,431 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::{chip8::FRAMES_PER_SECOND, display::Display, palette::Palette, util::{HIRES_DISPLAY_HEIGHT, HIRES_DISPLAY_WIDTH}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoFormat {
+    Gif, // Animated GIF, looping forever
+    Y4m, // Uncompressed YUV4MPEG2, e.g. for `ffmpeg -i game.y4m game.mp4`
+}
+
+impl VideoFormat {
+    pub fn from_name(name: &str) -> Option<VideoFormat> {
+        match name.to_lowercase().as_str() {
+            "gif" => Some(VideoFormat::Gif),
+            "y4m" => Some(VideoFormat::Y4m),
+            _     => None
+        }
+    }
+
+    /**
+     * Pick the format from a file's extension
+     */
+    pub fn from_file(file: &str) -> Option<VideoFormat> {
+        file.rsplit_once('.').and_then(|(_, extension)| VideoFormat::from_name(extension))
+    }
+}
+
+/**
+ * Records frames of the display to a video file. Videos are always the size of the high
+ * resolution display (times the scale), so programs can switch resolution part way through - low
+ * resolution pixels are just twice the size
+ */
+#[derive(Debug)]
+pub struct VideoRecorder {
+    writer: BufWriter<File>,
+    format: VideoFormat,
+    scale: usize, // Size of a high resolution pixel in the video
+    palette: Palette,
+    frames: u64,
+    pending: Option<(Vec<u8>, u64)>, // GIF frame waiting to find out how long it's shown for, and the frame it started on
+}
+
+impl VideoRecorder {
+    pub fn new(file: &str, format: VideoFormat, scale: usize, palette: Palette) -> io::Result<Self> {
+        let mut recorder = Self {
+            writer: BufWriter::new(File::create(file)?),
+            format,
+            scale,
+            palette,
+            frames: 0,
+            pending: None
+        };
+        match format {
+            VideoFormat::Gif => recorder.write_gif_header()?,
+            VideoFormat::Y4m => recorder.write_y4m_header()?
+        }
+        Ok(recorder)
+    }
+
+    pub fn get_frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn add_frame(&mut self, display: &Display) -> io::Result<()> {
+        let pixels = self.get_pixels(display);
+        match self.format {
+            VideoFormat::Gif => {
+                // Unchanged frames just make the previous one last longer, which keeps GIFs small
+                if self.pending.as_ref().map(|(pending, _)| *pending != pixels).unwrap_or(true) {
+                    self.write_pending_gif_frame()?;
+                    self.pending = Some((pixels, self.frames));
+                }
+            },
+            VideoFormat::Y4m => self.write_y4m_frame(&pixels)?
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        if self.format == VideoFormat::Gif {
+            self.write_pending_gif_frame()?;
+            self.writer.write_all(&[0x3B])?; // Trailer
+        }
+        self.writer.flush()
+    }
+
+    fn get_width(&self) -> usize {
+        HIRES_DISPLAY_WIDTH * self.scale
+    }
+
+    fn get_height(&self) -> usize {
+        HIRES_DISPLAY_HEIGHT * self.scale
+    }
+
+    /**
+     * Colour index of every pixel in the video frame, a row at a time
+     */
+    fn get_pixels(&self, display: &Display) -> Vec<u8> {
+        let pixel_size = self.get_width() / display.get_width();
+        let mut pixels = Vec::with_capacity(self.get_width() * self.get_height());
+        for y in 0..self.get_height() {
+            for x in 0..self.get_width() {
+                pixels.push(display.get_pixel(x / pixel_size, y / pixel_size));
+            }
+        }
+        pixels
+    }
+
+    fn write_gif_header(&mut self) -> io::Result<()> {
+        let (width, height) = (self.get_width() as u16, self.get_height() as u16);
+        self.writer.write_all(b"GIF89a")?;
+        self.writer.write_all(&width.to_le_bytes())?;
+        self.writer.write_all(&height.to_le_bytes())?;
+
+        // A global colour table of 4 colours, background colour 0, square pixels
+        self.writer.write_all(&[0x81, 0, 0])?;
+        for (r, g, b) in self.palette.colors {
+            self.writer.write_all(&[r, g, b])?;
+        }
+
+        // Netscape extension, to loop forever
+        self.writer.write_all(&[0x21, 0xFF, 0x0B])?;
+        self.writer.write_all(b"NETSCAPE2.0")?;
+        self.writer.write_all(&[0x03, 0x01, 0x00, 0x00, 0x00])
+    }
+
+    fn write_pending_gif_frame(&mut self) -> io::Result<()> {
+        let (pixels, start) = match self.pending.take() {
+            Some(pending) => pending,
+            None => return Ok(())
+        };
+
+        // GIF delays are in hundredths of a second, so round each frame's start and end to the
+        // nearest hundredth rather than its length - that way the video keeps to 60 Hz overall
+        let to_centiseconds = |frame: u64| (frame * 100 + FRAMES_PER_SECOND as u64 / 2) / FRAMES_PER_SECOND as u64;
+        let delay = (to_centiseconds(self.frames) - to_centiseconds(start)).min(u16::MAX as u64) as u16;
+
+        // Graphic control extension, setting how long the frame is shown
+        self.writer.write_all(&[0x21, 0xF9, 0x04, 0x00])?;
+        self.writer.write_all(&delay.to_le_bytes())?;
+        self.writer.write_all(&[0x00, 0x00])?;
+
+        // Image descriptor covering the whole screen, using the global colour table
+        let (width, height) = (self.get_width() as u16, self.get_height() as u16);
+        self.writer.write_all(&[0x2C, 0, 0, 0, 0])?;
+        self.writer.write_all(&width.to_le_bytes())?;
+        self.writer.write_all(&height.to_le_bytes())?;
+        self.writer.write_all(&[0x00])?;
+
+        // LZW compressed pixels, in blocks of up to 255 bytes
+        self.writer.write_all(&[GIF_MIN_CODE_SIZE])?;
+        for block in encode_lzw(&pixels).chunks(255) {
+            self.writer.write_all(&[block.len() as u8])?;
+            self.writer.write_all(block)?;
+        }
+        self.writer.write_all(&[0x00])
+    }
+
+    fn write_y4m_header(&mut self) -> io::Result<()> {
+        // Full resolution colour (4:4:4), as the pixels are too small to share colour samples
+        writeln!(self.writer, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444", self.get_width(), self.get_height(), FRAMES_PER_SECOND)
+    }
+
+    fn write_y4m_frame(&mut self, pixels: &[u8]) -> io::Result<()> {
+        let colors = self.palette.colors.map(get_ycbcr);
+        self.writer.write_all(b"FRAME\n")?;
+
+        // Y, then Cb, then Cr for the whole frame
+        for plane in [0, 1, 2] {
+            let samples: Vec<u8> = pixels.iter().map(|pixel| colors[*pixel as usize][plane]).collect();
+            self.writer.write_all(&samples)?;
+        }
+        Ok(())
+    }
+}
+
+// Smallest LZW code size GIF allows - enough for 4 colours
+const GIF_MIN_CODE_SIZE: u8 = 2;
+
+// GIF LZW codes can't be longer than 12 bits
+const MAX_LZW_CODE: u16 = 4096;
+
+/**
+ * LZW compress colour indexes the way GIF expects, with variable length codes packed from the
+ * least significant bit first
+ */
+fn encode_lzw(pixels: &[u8]) -> Vec<u8> {
+    let clear_code = 1u16 << GIF_MIN_CODE_SIZE;
+    let end_code = clear_code + 1;
+
+    let mut output = BitWriter::new();
+    let mut dictionary: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next_code = end_code + 1;
+    let mut code_size = GIF_MIN_CODE_SIZE + 1;
+    let mut prefix: Option<u16> = None;
+
+    output.write(clear_code, code_size);
+    for pixel in pixels.iter().copied() {
+        let current = match prefix {
+            Some(current) => current,
+            None => {
+                prefix = Some(pixel as u16);
+                continue;
+            }
+        };
+
+        if let Some(code) = dictionary.get(&(current, pixel)) {
+            prefix = Some(*code);
+            continue;
+        }
+
+        output.write(current, code_size);
+        match next_code < MAX_LZW_CODE {
+            true  => {
+                dictionary.insert((current, pixel), next_code);
+                next_code += 1;
+                if next_code > (1 << code_size) && code_size < 12 {
+                    code_size += 1;
+                }
+            },
+            // Start again once the dictionary is full
+            false => {
+                output.write(clear_code, code_size);
+                dictionary.clear();
+                next_code = end_code + 1;
+                code_size = GIF_MIN_CODE_SIZE + 1;
+            }
+        }
+        prefix = Some(pixel as u16);
+    }
+
+    if let Some(current) = prefix {
+        output.write(current, code_size);
+    }
+    output.write(end_code, code_size);
+    output.finish()
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u8, // Number of bits waiting in buffer
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self { bytes: Vec::new(), buffer: 0, bits: 0 }
+    }
+
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+/**
+ * Convert a colour to studio range BT.601 Y'CbCr, which is what players assume for Y4M
+ */
+fn get_ycbcr((r, g, b): (u8, u8, u8)) -> [u8; 3] {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    let y = 16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0;
+    let cb = 128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0;
+    let cr = 128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0;
+    [y.round() as u8, cb.round() as u8, cr.round() as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    /**
+     * Undo encode_lzw, the way a GIF decoder would
+     */
+    fn decode_lzw(data: &[u8]) -> Vec<u8> {
+        let clear_code = 1usize << GIF_MIN_CODE_SIZE;
+        let end_code = clear_code + 1;
+        let reset = || -> Vec<Vec<u8>> { (0..=end_code as u8).map(|code| vec![code]).collect() };
+
+        let mut dictionary = reset();
+        let mut code_size = GIF_MIN_CODE_SIZE + 1;
+        let mut previous: Option<Vec<u8>> = None;
+        let mut output = Vec::new();
+        let mut bit = 0;
+        loop {
+            let code = (0..code_size as usize).fold(0, |code, i| code | ((((data[(bit + i) / 8] >> ((bit + i) % 8)) & 1) as usize) << i));
+            bit += code_size as usize;
+
+            if code == clear_code {
+                dictionary = reset();
+                code_size = GIF_MIN_CODE_SIZE + 1;
+                previous = None;
+                continue;
+            }
+            if code == end_code {
+                return output;
+            }
+
+            let entry = match (dictionary.get(code), &previous) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(previous)) => [previous.as_slice(), &previous[..1]].concat(),
+                (None, None) => panic!("Code {} isn't in the dictionary", code)
+            };
+            output.extend_from_slice(&entry);
+            if let Some(previous) = previous {
+                if dictionary.len() < MAX_LZW_CODE as usize {
+                    dictionary.push([previous.as_slice(), &entry[..1]].concat());
+                }
+            }
+            if dictionary.len() == 1 << code_size && code_size < 12 {
+                code_size += 1;
+            }
+            previous = Some(entry);
+        }
+    }
+
+    /**
+     * The delay and pixels of each frame in a GIF written by VideoRecorder
+     */
+    fn read_gif_frames(gif: &[u8]) -> Vec<(u16, Vec<u8>)> {
+        assert_eq!(&gif[..6], b"GIF89a");
+        let mut pos = 6 + 7 + 4 * 3;
+        let mut frames = Vec::new();
+        let mut delay = 0;
+
+        let read_sub_blocks = |pos: &mut usize| {
+            let mut data = Vec::new();
+            while gif[*pos] != 0 {
+                let len = gif[*pos] as usize;
+                data.extend_from_slice(&gif[*pos + 1..*pos + 1 + len]);
+                *pos += 1 + len;
+            }
+            *pos += 1;
+            data
+        };
+
+        loop {
+            match gif[pos] {
+                0x21 => {
+                    if gif[pos + 1] == 0xF9 {
+                        delay = u16::from_le_bytes([gif[pos + 4], gif[pos + 5]]);
+                    }
+                    pos += 2;
+                    read_sub_blocks(&mut pos);
+                },
+                0x2C => {
+                    pos += 10;
+                    assert_eq!(gif[pos], GIF_MIN_CODE_SIZE);
+                    pos += 1;
+                    frames.push((delay, decode_lzw(&read_sub_blocks(&mut pos))));
+                },
+                0x3B => return frames,
+                byte => panic!("Unexpected block 0x{:02X}", byte)
+            }
+        }
+    }
+
+    fn record(format: VideoFormat, displays: &[Display]) -> Vec<u8> {
+        let file = std::env::temp_dir().join(format!("rusty-chip-test-{}-{:?}", std::process::id(), format));
+        let file = file.to_str().unwrap();
+
+        let mut recorder = VideoRecorder::new(file, format, 1, Palette::default()).unwrap();
+        for display in displays {
+            recorder.add_frame(display).unwrap();
+        }
+        assert_eq!(recorder.get_frames(), displays.len() as u64);
+        recorder.finish().unwrap();
+
+        let video = std::fs::read(file).unwrap();
+        std::fs::remove_file(file).unwrap();
+        video
+    }
+
+    #[test]
+    fn compresses_pixels_with_lzw() {
+        // Random pixels fill the dictionary, so it has to be cleared and started again
+        let mut rng = Rng::new(1);
+        let pixels: Vec<u8> = (0..100_000).map(|_| rng.next_u8() & 0x3).collect();
+        assert_eq!(decode_lzw(&encode_lzw(&pixels)), pixels);
+
+        let blank = vec![0; HIRES_DISPLAY_WIDTH * HIRES_DISPLAY_HEIGHT];
+        assert_eq!(decode_lzw(&encode_lzw(&blank)), blank);
+    }
+
+    #[test]
+    fn records_gif_frames_until_the_screen_changes() {
+        let blank = Display::new();
+        let mut lit = Display::new();
+        lit.set_pixel(1, 2, 1, 1);
+
+        let gif = record(VideoFormat::Gif, &[blank.clone(), blank, lit]);
+        let frames = read_gif_frames(&gif);
+        assert_eq!(frames.len(), 2);
+
+        // Frames start at 0, 3.33 and 5 hundredths of a second, rounded
+        assert_eq!(frames[0].0, 3);
+        assert_eq!(frames[1].0, 2);
+
+        // Low resolution pixels are 2x2 in the high resolution video
+        assert!(frames[0].1.iter().all(|pixel| *pixel == 0));
+        let lit_pixels: Vec<usize> = frames[1].1.iter().enumerate().filter(|(_, pixel)| **pixel == 1).map(|(i, _)| i).collect();
+        let width = HIRES_DISPLAY_WIDTH;
+        assert_eq!(lit_pixels, vec![4 * width + 2, 4 * width + 3, 5 * width + 2, 5 * width + 3]);
+    }
+
+    #[test]
+    fn records_every_y4m_frame() {
+        let y4m = record(VideoFormat::Y4m, &[Display::new(), Display::new()]);
+        let header = format!("YUV4MPEG2 W{} H{} F60:1 Ip A1:1 C444\n", HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT);
+        assert!(y4m.starts_with(header.as_bytes()));
+
+        let frame_size = b"FRAME\n".len() + 3 * HIRES_DISPLAY_WIDTH * HIRES_DISPLAY_HEIGHT;
+        assert_eq!(y4m.len(), header.len() + 2 * frame_size);
+        assert_eq!(&y4m[header.len() + frame_size..header.len() + frame_size + 6], b"FRAME\n");
+    }
+}